@@ -0,0 +1,189 @@
+// Compares the rays per second of the tile renderer with the scheduling it replaced: one channel
+// message per pixel, with the finished pixels written into a locked image. Both render the same
+// scene with the same integrator and sampler, so only the scheduling differs.
+//
+//     cargo run --release --example throughput -- <scene.json> <width> <spp> <threads> [runs]
+
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use raytracing::camera::Camera;
+use raytracing::film::Film;
+use raytracing::filter::{FilterKind, PixelFilter};
+use raytracing::hit::Hittable;
+use raytracing::image::Image;
+use raytracing::integrator::{Integrator, PathIntegrator, SceneView};
+use raytracing::renderer::{RenderSettings, Renderer};
+use raytracing::sampler::SamplerKind;
+use raytracing::scene::Scene;
+use raytracing::vec3::Color;
+
+use crossbeam::channel::unbounded;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+const SEED: u64 = 0;
+
+struct Settings {
+    width: usize,
+    height: usize,
+    samples: usize,
+    threads: usize,
+}
+
+fn scene(path: &str) -> Scene {
+    Scene::load(path).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    })
+}
+
+fn load(path: &str, settings: &Settings) -> (Arc<dyn Hittable>, Scene, Camera) {
+    let mut scene = scene(path);
+    let world = std::mem::take(&mut scene.world);
+    let world = Arc::new(world.into_bvh(&mut SmallRng::seed_from_u64(SEED)));
+    let camera = scene
+        .camera
+        .build(settings.width as f64 / settings.height as f64);
+    (world, scene, camera)
+}
+
+// The renderer before the tiles: every pixel is a message on a channel, and is stored under a
+// lock as soon as its samples are taken.
+fn render_locked(path: &str, settings: &Settings) -> (u64, Duration) {
+    let (world, scene, camera) = load(path, settings);
+    let integrator = PathIntegrator::new(None, 3);
+    let view = SceneView {
+        world: world.as_ref(),
+        lights: &scene.lights,
+        background: scene.background.as_ref(),
+    };
+    let Settings {
+        width,
+        height,
+        samples,
+        threads,
+    } = *settings;
+
+    let start = Instant::now();
+    let (s, r) = unbounded();
+    for j in (0..height).rev() {
+        for i in 0..width {
+            s.send((i, j)).unwrap();
+        }
+    }
+    drop(s);
+    let canvas = Mutex::new(Image::new(width, height));
+    let finished = Mutex::new(vec![0; height]);
+    let rays = AtomicU64::new(0);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let (r, canvas, finished, rays) = (r.clone(), &canvas, &finished, &rays);
+            let (view, camera, integrator) = (&view, &camera, &integrator);
+            scope.spawn(move || {
+                let mut sampler = SamplerKind::Independent.build(SEED, samples);
+                let mut thread_rays = 0;
+                while let Ok((i, j)) = r.recv() {
+                    let mut color = Color::default();
+                    for sample in 0..samples {
+                        sampler.start_sample(i, j, sample);
+                        let (du, dv) = sampler.get_2d();
+                        let u = (i as f64 + du) / (width - 1) as f64;
+                        let v = (j as f64 + dv) / (height - 1) as f64;
+                        let ray = camera.get_ray(u, v, sampler.as_mut());
+                        color += integrator.radiance(ray, view, sampler.as_mut(), &mut thread_rays);
+                    }
+                    canvas
+                        .lock()
+                        .unwrap()
+                        .set_pixel(i, height - 1 - j, color / samples as f64);
+                    let mut finished = finished.lock().unwrap();
+                    finished[j] += 1;
+                    if finished[j] == width {
+                        eprint!("\r Scanline remaining: {:<8}", j);
+                    }
+                }
+                rays.fetch_add(thread_rays, Ordering::Relaxed);
+            });
+        }
+    });
+    eprintln!();
+    (rays.into_inner(), start.elapsed())
+}
+
+fn render_tiles(path: &str, settings: &Settings) -> (u64, Duration) {
+    let (world, scene, camera) = load(path, settings);
+    let filter = PixelFilter::new(FilterKind::Box, FilterKind::Box.default_radius());
+    let renderer = Renderer::new(
+        world,
+        scene.lights,
+        camera,
+        scene.background,
+        Box::new(PathIntegrator::new(None, 3)),
+        RenderSettings {
+            threads: settings.threads,
+            max_samples: settings.samples,
+            min_samples: settings.samples,
+            error_threshold: None,
+            filter,
+            sampler: SamplerKind::Independent,
+        },
+    );
+    let mut film = Film::new(
+        settings.width,
+        settings.height,
+        SEED,
+        SamplerKind::Independent,
+        filter,
+        settings.samples,
+    );
+    let stats = renderer.render_pass(&mut film, settings.samples);
+    (stats.rays, stats.elapsed)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !(4..=5).contains(&args.len()) {
+        eprintln!("usage: throughput <scene.json> <width> <spp> <threads> [runs]");
+        process::exit(2);
+    }
+    let number = |i: usize| -> usize {
+        args[i].parse().unwrap_or_else(|_| {
+            eprintln!("error: `{}` is not a number", args[i]);
+            process::exit(2);
+        })
+    };
+    let path = &args[0];
+    let aspect_ratio = scene(path).camera.aspect_ratio;
+    let width = number(1).max(2);
+    let settings = Settings {
+        width,
+        height: ((width as f64 / aspect_ratio) as usize).max(2),
+        samples: number(2).max(1),
+        threads: number(3).max(1),
+    };
+    let runs = if args.len() == 5 { number(4) } else { 3 };
+
+    // interleaved, so that a change in the machine's load hits both alike
+    let mrays = |(rays, elapsed): (u64, Duration)| rays as f64 / elapsed.as_secs_f64() / 1E6;
+    let (mut locked, mut tiles) = (vec![], vec![]);
+    for _ in 0..runs {
+        locked.push(mrays(render_locked(path, &settings)));
+        tiles.push(mrays(render_tiles(path, &settings)));
+    }
+    let show = |runs: &[f64]| {
+        runs.iter()
+            .map(|r| format!("{:.3}", r))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    println!(
+        "{}x{}, {} spp, {} threads, Mrays/s per run",
+        settings.width, settings.height, settings.samples, settings.threads
+    );
+    println!("channel and locks: {}", show(&locked));
+    println!("tiles:             {}", show(&tiles));
+}
@@ -0,0 +1,75 @@
+use crate::image::{Filter, Image, Wrap};
+use crate::ray::Ray;
+use crate::vec3::Color;
+
+// The radiance arriving along rays that escape the scene.
+pub trait Background: Send + Sync {
+    fn value(&self, ray: &Ray) -> Color;
+}
+
+pub struct SolidBackground {
+    color: Color,
+}
+
+impl SolidBackground {
+    pub fn new(red: f64, green: f64, blue: f64) -> Self {
+        Self {
+            color: Color::new(red, green, blue),
+        }
+    }
+}
+
+impl Background for SolidBackground {
+    fn value(&self, _ray: &Ray) -> Color {
+        self.color
+    }
+}
+
+// Blends linearly between two colors by the height of the ray direction.
+pub struct GradientBackground {
+    bottom: Color,
+    top: Color,
+}
+
+impl GradientBackground {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+
+    // The white to light blue sky
+    pub fn sky() -> Self {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for GradientBackground {
+    fn value(&self, ray: &Ray) -> Color {
+        let t = (ray.direction().normalize().y() + 1.0) * 0.5;
+        self.bottom * (1.0 - t) + self.top * t
+    }
+}
+
+// An equirectangular (latitude-longitude) environment map, laid out with the same (u, v)
+// convention as the sphere texture coordinates.
+pub struct EnvironmentMap {
+    image: Image,
+    intensity: f64,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Image, intensity: f64) -> Self {
+        Self { image, intensity }
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, ray: &Ray) -> Color {
+        let d = ray.direction().normalize();
+        let theta = f64::acos(-d.y().clamp(-1.0, 1.0));
+        let phi = f64::atan2(-d.z(), d.x()) + std::f64::consts::PI;
+
+        let u = phi / (2.0 * std::f64::consts::PI);
+        let v = theta / std::f64::consts::PI;
+        self.image.sample(u, v, Filter::Bilinear, Wrap::Clamp) * self.intensity
+    }
+}
@@ -1,21 +1,45 @@
+use std::cell::Cell;
 use std::cmp::Ordering;
 use std::ops::RangeInclusive;
 use std::sync::Arc;
 
+use rand::rngs::SmallRng;
+
 use crate::aabb::AABB;
 use crate::hit::{HitRecord, Hittable};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::util::random_usize_range;
+use crate::vec3::{Point3, Vec3};
+
+thread_local! {
+    // The nodes the thread's rays visited, while it counts them to show the cost of traversals.
+    static NODES_VISITED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+// Runs `f`, counting the BVH nodes the thread's rays visit meanwhile. Outside of it, traversals
+// count nothing.
+pub fn count_nodes_visited<T>(f: impl FnOnce() -> T) -> (T, u64) {
+    let outer = NODES_VISITED.replace(Some(0));
+    let value = f();
+    let visited = NODES_VISITED.replace(outer).unwrap_or(0);
+    (value, visited)
+}
 
 pub struct BVH {
     left: Option<Arc<dyn Hittable>>,
     right: Option<Arc<dyn Hittable>>,
     bounding_box: AABB,
+    // Sampled as a light, the node picks a child in proportion to its area: the left one with
+    // this chance. A node of a single object has it on both sides and always picks the left.
+    left_weight: f64,
+    area: Option<f64>,
 }
 
 impl BVH {
-    pub fn new(objects: &[Arc<dyn Hittable>]) -> Self {
-        let cmp = match random_usize_range(0..3) {
+    // The split axes are picked at random, from `rng`.
+    pub fn new(objects: &[Arc<dyn Hittable>], rng: &mut SmallRng) -> Self {
+        let cmp = match random_usize_range(0..3, rng) {
             0 => box_x_compare,
             1 => box_y_compare,
             2 => box_z_compare,
@@ -43,8 +67,8 @@ impl BVH {
             objects.sort_by(cmp);
 
             let mid = objects.len() / 2;
-            left = Some(Arc::new(BVH::new(&objects[..mid])));
-            right = Some(Arc::new(BVH::new(&objects[mid..])));
+            left = Some(Arc::new(BVH::new(&objects[..mid], rng)));
+            right = Some(Arc::new(BVH::new(&objects[mid..], rng)));
         }
 
         let bounding_box = AABB::surrounding_box(
@@ -52,16 +76,38 @@ impl BVH {
             right.as_ref().unwrap().bounding_box().unwrap(),
         );
 
+        let (area, left_weight) = if objects.len() == 1 {
+            (objects[0].area(), 1.0)
+        } else {
+            let left_area = left.as_ref().unwrap().area();
+            let right_area = right.as_ref().unwrap().area();
+            match (left_area, right_area) {
+                (Some(l), Some(r)) if l + r > 0.0 => (Some(l + r), l / (l + r)),
+                (Some(l), Some(r)) => (Some(l + r), 0.5),
+                _ => (None, 0.5),
+            }
+        };
+
         Self {
             left,
             right,
             bounding_box,
+            left_weight,
+            area,
         }
     }
 }
 
 impl Hittable for BVH {
-    fn hit(&self, ray: &Ray, t_range: &RangeInclusive<f64>) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        if let Some(visited) = NODES_VISITED.get() {
+            NODES_VISITED.set(Some(visited + 1));
+        }
         if !self.bounding_box.hit(ray, t_range) {
             return None;
         }
@@ -69,14 +115,14 @@ impl Hittable for BVH {
         let mut range = t_range.clone();
         let mut res = None;
         if let Some(l) = self.left.as_ref() {
-            res = l.hit(ray, &range);
-            if res.is_some() {
-                range = RangeInclusive::new(*range.start(), res.as_ref().unwrap().t);
+            res = l.hit(ray, &range, sampler);
+            if let Some(hit) = &res {
+                range = RangeInclusive::new(*range.start(), hit.t);
             }
         }
 
         if let Some(r) = self.right.as_ref() {
-            let res1 = r.hit(ray, &range);
+            let res1 = r.hit(ray, &range, sampler);
             if res1.is_some() {
                 res = res1
             }
@@ -87,6 +133,41 @@ impl Hittable for BVH {
     fn bounding_box(&self) -> Option<AABB> {
         Some(self.bounding_box.clone())
     }
+
+    fn area(&self) -> Option<f64> {
+        self.area
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        // the directions that miss the box miss all the children
+        let ray = Ray::new(*origin, *direction, 0.0);
+        if self.area.is_none()
+            || !self
+                .bounding_box
+                .hit(&ray, &RangeInclusive::new(0.001, f64::INFINITY))
+        {
+            return 0.0;
+        }
+        let mut pdf = 0.0;
+        if self.left_weight > 0.0 {
+            let left = self.left.as_ref().unwrap();
+            pdf += self.left_weight * left.pdf_value(origin, direction, sampler);
+        }
+        if self.left_weight < 1.0 {
+            let right = self.right.as_ref().unwrap();
+            pdf += (1.0 - self.left_weight) * right.pdf_value(origin, direction, sampler);
+        }
+        pdf
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let child = if sampler.get_1d() < self.left_weight {
+            &self.left
+        } else {
+            &self.right
+        };
+        child.as_ref().unwrap().random(origin, sampler)
+    }
 }
 
 fn box_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>, axis: usize) -> Ordering {
@@ -113,3 +194,50 @@ fn box_y_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>) -> Ordering {
 fn box_z_compare(a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>) -> Ordering {
     box_compare(a, b, 2)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use rand::SeedableRng;
+
+    // four spheres in a row along x, in two nodes of two under the root
+    fn row() -> BVH {
+        let material = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5))));
+        let objects: Vec<Arc<dyn Hittable>> = (0..4)
+            .map(|i| {
+                let center = Point3::new(3.0 * i as f64, 0.0, 0.0);
+                Arc::new(Sphere::new(center, 1.0, material.clone())) as Arc<dyn Hittable>
+            })
+            .collect();
+        BVH::new(&objects, &mut SmallRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn counts_nodes_only_when_asked() {
+        let bvh = row();
+        let mut sampler = IndependentSampler::new(0);
+        let range = RangeInclusive::new(0.001, f64::INFINITY);
+        let along = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let above = Ray::new(Point3::new(-5.0, 5.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+
+        let (hit, visited) = count_nodes_visited(|| bvh.hit(&along, &range, &mut sampler));
+        assert!((hit.unwrap().t - 4.0).abs() < 1e-12);
+        assert_eq!(visited, 3);
+        let (hit, visited) = count_nodes_visited(|| bvh.hit(&above, &range, &mut sampler));
+        assert!(hit.is_none());
+        assert_eq!(visited, 1);
+
+        // nothing is counted outside, and an inner count leaves the outer one be
+        bvh.hit(&along, &range, &mut sampler);
+        assert_eq!(NODES_VISITED.get(), None);
+        let ((_, inner), outer) = count_nodes_visited(|| {
+            bvh.hit(&above, &range, &mut sampler);
+            count_nodes_visited(|| bvh.hit(&along, &range, &mut sampler))
+        });
+        assert_eq!((inner, outer), (3, 1));
+    }
+}
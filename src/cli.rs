@@ -0,0 +1,447 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use raytracing::filter::{FilterKind, PixelFilter};
+use raytracing::integrator::IntegratorKind;
+use raytracing::sampler::SamplerKind;
+use raytracing::tonemap::ToneMap;
+
+use crate::scenes;
+
+pub const DEFAULT_WIDTH: usize = 400;
+pub const DEFAULT_SAMPLES_PER_PIXEL: usize = 500;
+
+// The flags that take a value, all but `--help` and `--dither`.
+const VALUE_FLAGS: &[&str] = &[
+    "-W",
+    "--width",
+    "-H",
+    "--height",
+    "-a",
+    "--aspect",
+    "-s",
+    "--spp",
+    "-d",
+    "--max-depth",
+    "--roulette-depth",
+    "--integrator",
+    "--ao-distance",
+    "-j",
+    "--threads",
+    "--seed",
+    "--pass-spp",
+    "--adaptive-threshold",
+    "--min-spp",
+    "--sampler",
+    "--filter",
+    "--filter-radius",
+    "--sample-map",
+    "--checkpoint",
+    "--checkpoint-interval",
+    "--resume",
+    "--scene",
+    "-o",
+    "--output",
+    "-f",
+    "--format",
+    "-e",
+    "--exposure",
+    "--tonemap",
+];
+
+pub fn usage() -> String {
+    format!(
+        "\
+Usage: raytracing [OPTIONS]
+
+Options:
+  -W, --width <PIXELS>       image width [default: {width}, or derived from the height]
+  -H, --height <PIXELS>      image height [default: derived from the width]
+  -a, --aspect <RATIO>       width over height, as `16:9` or `1.78` [default: the scene's]
+  -s, --spp <N>              samples per pixel, the most a pixel takes with adaptive sampling
+                             [default: {spp}, or the checkpoint's]
+  -d, --max-depth <N>        maximum number of bounces per path
+                             [default: none, or 16 with `--integrator direct`]
+      --roulette-depth <N>   bounces before Russian roulette can end a path [default: 3]
+      --integrator <INTEGRATOR>
+                             what the rays bring back [default: path]
+                             path: all the light, direct: the light straight from the lights,
+                             ao: ambient occlusion, or the debug views normals, uv, depth
+                             (white at twice the distance to the camera's target) and
+                             bvh-cost (BVH nodes visited, blue to red)
+      --ao-distance <DISTANCE>
+                             reach of ambient occlusion [default: a tenth of the distance to
+                             the camera's target]
+  -j, --threads <N>          number of render threads [default: available cores]
+      --seed <N>             seed of the scene and of the sampling [default: 0, or the checkpoint's]
+      --pass-spp <N>         samples per pixel of each progressive pass [default: 16]
+      --adaptive-threshold <ERROR>
+                             sample adaptively: a pixel stops once the standard error of its
+                             luminance is below ERROR times its luminance, e.g. 0.01
+      --min-spp <N>          samples per pixel before a pixel can stop [default: 16]
+      --sampler <SAMPLER>    how the samples of a pixel are spread
+                             [default: independent, or the checkpoint's]
+                             one of: independent, stratified, halton, sobol
+      --filter <FILTER>      pixel reconstruction filter [default: box, or the checkpoint's]
+                             one of: box, tent, gaussian, mitchell
+      --filter-radius <PIXELS>
+                             reach of the filter from the pixel center
+                             [default: 0.5 box, 1 tent, 1.5 gaussian, 2 mitchell]
+      --sample-map <FILE>    also save an image of the samples taken per pixel, white for `--spp`
+      --checkpoint <FILE>    save the samples taken so far to FILE after passes and at the end
+      --checkpoint-interval <SECONDS>
+                             least time between two checkpoints [default: 60]
+      --resume <FILE>        continue the render saved in a checkpoint, up to `--spp` samples;
+                             the scene and its options must be the same
+      --scene <SCENE>        built-in scene or JSON scene file to render [default: random]
+                             built-in scenes: {scenes}
+  -o, --output <FILE>        output file [default: standard output]
+  -f, --format <FORMAT>      output format, guessed from the file extension if omitted
+                             one of: ppm, png, hdr
+  -e, --exposure <STOPS>     exposure adjustment before tone mapping [default: 0]
+      --tonemap <OPERATOR>   tone mapping operator [default: clamp]
+                             one of: clamp, reinhard, aces
+      --dither               dither 8-bit output to hide banding
+  -h, --help                 print this help",
+        width = DEFAULT_WIDTH,
+        spp = DEFAULT_SAMPLES_PER_PIXEL,
+        scenes = scenes::NAMES.join(", "),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // binary PPM (P6)
+    Ppm,
+    Png,
+    // Radiance RGBE, with the linear, unclamped colors
+    Hdr,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(OutputFormat::Ppm),
+            "png" => Some(OutputFormat::Png),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+}
+
+pub struct Options {
+    // at most two of width, height and aspect ratio are set, see `resolution`
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub aspect_ratio: Option<f64>,
+    // `None` to take the samples of the checkpoint when resuming, or the default
+    pub samples_per_pixel: Option<usize>,
+    // `None` leaves it to Russian roulette to end the paths
+    pub max_depth: Option<usize>,
+    pub roulette_depth: usize,
+    pub integrator: IntegratorKind,
+    // `None` to scale it to the scene
+    pub ao_distance: Option<f64>,
+    pub threads: usize,
+    // `None` to take the seed of the checkpoint when resuming, or 0
+    pub seed: Option<u64>,
+    pub pass_samples: usize,
+    pub min_samples: usize,
+    pub error_threshold: Option<f64>,
+    // `None` to take the sampler and the filter of the checkpoint when resuming, or the defaults
+    pub sampler: Option<SamplerKind>,
+    pub filter: Option<PixelFilter>,
+    pub sample_map: Option<(PathBuf, OutputFormat)>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    pub resume: Option<PathBuf>,
+    pub scene: String,
+    // `None` writes to the standard output
+    pub output: Option<PathBuf>,
+    pub format: OutputFormat,
+    // the color pipeline of the 8-bit formats, HDR output is left linear
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub dither: bool,
+}
+
+// The outcome of parsing the command line.
+pub enum Command {
+    Render(Box<Options>),
+    Help,
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", value, flag))
+}
+
+fn parse_positive(flag: &str, value: &str) -> Result<usize, String> {
+    match parse_number(flag, value)? {
+        0 => Err(format!("`{}` must be at least 1", flag)),
+        n => Ok(n),
+    }
+}
+
+// The camera maps the first and last pixel centers to the edges of the view, so an image needs at
+// least two of them each way.
+fn parse_size(flag: &str, value: &str) -> Result<usize, String> {
+    match parse_number(flag, value)? {
+        0 | 1 => Err(format!("`{}` must be at least 2", flag)),
+        n => Ok(n),
+    }
+}
+
+fn parse_aspect(value: &str) -> Result<f64, String> {
+    let ratio = match value.split_once(':') {
+        Some((w, h)) => parse_number::<f64>("--aspect", w)? / parse_number::<f64>("--aspect", h)?,
+        None => parse_number("--aspect", value)?,
+    };
+    if !ratio.is_finite() || ratio <= 0.0 {
+        return Err(format!("invalid aspect ratio `{}`", value));
+    }
+    Ok(ratio)
+}
+
+fn format_of(path: &Path) -> Result<OutputFormat, String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(OutputFormat::from_name)
+        .ok_or_else(|| {
+            format!(
+                "cannot tell the output format of `{}`, use `--format`",
+                path.display()
+            )
+        })
+}
+
+impl Options {
+    // Parses the arguments, without the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+        let mut width = None;
+        let mut height = None;
+        let mut aspect = None;
+        let mut samples_per_pixel = None;
+        let mut max_depth = None;
+        let mut roulette_depth = 3;
+        let mut integrator = IntegratorKind::Path;
+        let mut ao_distance = None;
+        let mut threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let mut seed = None;
+        let mut pass_samples = 16;
+        let mut min_samples = 16;
+        let mut error_threshold = None;
+        let mut sampler = None;
+        let mut filter_kind = None;
+        let mut filter_radius = None;
+        let mut sample_map = None;
+        let mut checkpoint = None;
+        let mut checkpoint_interval = Duration::from_secs(60);
+        let mut resume = None;
+        let mut scene = "random".to_owned();
+        let mut output = None;
+        let mut format = None;
+        let mut exposure: f64 = 0.0;
+        let mut tone_map = ToneMap::Clamp;
+        let mut dither = false;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // accept both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg.clone(), None),
+            };
+            if flag == "-h" || flag == "--help" {
+                return Ok(Command::Help);
+            }
+            if flag == "--dither" {
+                if inline_value.is_some() {
+                    return Err("`--dither` takes no value".into());
+                }
+                dither = true;
+                continue;
+            }
+
+            if !VALUE_FLAGS.contains(&flag.as_str()) {
+                return Err(format!("unexpected argument `{}`", flag));
+            }
+            let value = inline_value
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for `{}`", flag))?;
+            match flag.as_str() {
+                "-W" | "--width" => width = Some(parse_size(&flag, &value)?),
+                "-H" | "--height" => height = Some(parse_size(&flag, &value)?),
+                "-a" | "--aspect" => aspect = Some(parse_aspect(&value)?),
+                "-s" | "--spp" => samples_per_pixel = Some(parse_positive(&flag, &value)?),
+                "-d" | "--max-depth" => max_depth = Some(parse_positive(&flag, &value)?),
+                "--roulette-depth" => roulette_depth = parse_number(&flag, &value)?,
+                "--integrator" => {
+                    integrator = match value.as_str() {
+                        "path" => IntegratorKind::Path,
+                        "direct" => IntegratorKind::Direct,
+                        "ao" => IntegratorKind::AmbientOcclusion,
+                        "normals" => IntegratorKind::Normals,
+                        "uv" => IntegratorKind::Uv,
+                        "depth" => IntegratorKind::Depth,
+                        "bvh-cost" => IntegratorKind::BvhCost,
+                        _ => return Err(format!("unknown integrator `{}`", value)),
+                    }
+                }
+                "--ao-distance" => {
+                    let distance: f64 = parse_number(&flag, &value)?;
+                    if !(distance > 0.0 && distance.is_finite()) {
+                        return Err(format!("`{}` must be positive", flag));
+                    }
+                    ao_distance = Some(distance);
+                }
+                "-j" | "--threads" => threads = parse_positive(&flag, &value)?,
+                "--seed" => seed = Some(parse_number(&flag, &value)?),
+                "--pass-spp" => pass_samples = parse_positive(&flag, &value)?,
+                "--adaptive-threshold" => {
+                    let threshold: f64 = parse_number(&flag, &value)?;
+                    if !(threshold > 0.0 && threshold.is_finite()) {
+                        return Err(format!("`{}` must be positive", flag));
+                    }
+                    error_threshold = Some(threshold);
+                }
+                "--min-spp" => min_samples = parse_positive(&flag, &value)?,
+                "--sampler" => {
+                    sampler = Some(match value.as_str() {
+                        "independent" => SamplerKind::Independent,
+                        "stratified" => SamplerKind::Stratified,
+                        "halton" => SamplerKind::Halton,
+                        "sobol" => SamplerKind::Sobol,
+                        _ => return Err(format!("unknown sampler `{}`", value)),
+                    })
+                }
+                "--filter" => {
+                    filter_kind = Some(match value.as_str() {
+                        "box" => FilterKind::Box,
+                        "tent" => FilterKind::Tent,
+                        "gaussian" => FilterKind::Gaussian,
+                        "mitchell" => FilterKind::Mitchell,
+                        _ => return Err(format!("unknown filter `{}`", value)),
+                    })
+                }
+                "--filter-radius" => {
+                    let radius: f64 = parse_number(&flag, &value)?;
+                    // below half a pixel, samples near the edges of a pixel would count for none
+                    if !(radius >= 0.5 && radius.is_finite()) {
+                        return Err(format!("`{}` must be at least 0.5", flag));
+                    }
+                    filter_radius = Some(radius);
+                }
+                "--sample-map" => {
+                    let path = PathBuf::from(value);
+                    sample_map = Some((path.clone(), format_of(&path)?));
+                }
+                "--checkpoint" => checkpoint = Some(PathBuf::from(value)),
+                "--checkpoint-interval" => {
+                    let seconds: f64 = parse_number(&flag, &value)?;
+                    checkpoint_interval = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| format!("invalid value `{}` for `{}`", value, flag))?;
+                }
+                "--resume" => resume = Some(PathBuf::from(value)),
+                "--scene" => scene = value,
+                "-o" | "--output" => {
+                    output = if value == "-" {
+                        None
+                    } else {
+                        Some(PathBuf::from(value))
+                    }
+                }
+                "-f" | "--format" => {
+                    format = Some(
+                        OutputFormat::from_name(&value)
+                            .ok_or_else(|| format!("unknown output format `{}`", value))?,
+                    )
+                }
+                "-e" | "--exposure" => {
+                    exposure = parse_number(&flag, &value)?;
+                    if !exposure.is_finite() {
+                        return Err(format!("invalid value `{}` for `{}`", value, flag));
+                    }
+                }
+                "--tonemap" => {
+                    tone_map = match value.as_str() {
+                        "clamp" => ToneMap::Clamp,
+                        "reinhard" => ToneMap::Reinhard,
+                        "aces" => ToneMap::Aces,
+                        _ => return Err(format!("unknown tone mapping operator `{}`", value)),
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        if width.is_some() && height.is_some() && aspect.is_some() {
+            return Err("at most two of `--width`, `--height` and `--aspect` can be given".into());
+        }
+        let format = match (format, &output) {
+            (Some(format), _) => format,
+            (None, Some(path)) => format_of(path)?,
+            (None, None) => OutputFormat::Ppm,
+        };
+        let filter = match (filter_kind, filter_radius) {
+            (None, None) => None,
+            (kind, radius) => {
+                let kind = kind.unwrap_or(FilterKind::Box);
+                Some(PixelFilter::new(
+                    kind,
+                    radius.unwrap_or_else(|| kind.default_radius()),
+                ))
+            }
+        };
+
+        Ok(Command::Render(Box::new(Options {
+            width,
+            height,
+            aspect_ratio: aspect,
+            samples_per_pixel,
+            max_depth,
+            roulette_depth,
+            integrator,
+            ao_distance,
+            threads,
+            seed,
+            pass_samples,
+            min_samples,
+            error_threshold,
+            sampler,
+            filter,
+            sample_map,
+            checkpoint,
+            checkpoint_interval,
+            resume,
+            scene,
+            output,
+            format,
+            exposure,
+            tone_map,
+            dither,
+        })))
+    }
+
+    // The image size in pixels, completing whatever was not given on the command line with the
+    // aspect ratio the scene was composed for.
+    pub fn resolution(&self, scene_aspect_ratio: f64) -> (usize, usize) {
+        let aspect = self.aspect_ratio.unwrap_or(scene_aspect_ratio);
+        let (width, height) = match (self.width, self.height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, (w as f64 / aspect).round() as usize),
+            (None, Some(h)) => ((h as f64 * aspect).round() as usize, h),
+            (None, None) => (
+                DEFAULT_WIDTH,
+                (DEFAULT_WIDTH as f64 / aspect).round() as usize,
+            ),
+        };
+        (width.max(2), height.max(2))
+    }
+}
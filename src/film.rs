@@ -0,0 +1,582 @@
+// The accumulated samples of a render in progress, which can be saved as a checkpoint and
+// loaded again to continue the render.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::filter::{FilterKind, PixelFilter};
+use crate::image::Image;
+use crate::sampler::SamplerKind;
+use crate::tonemap::luminance;
+use crate::vec3::Color;
+
+const CHECKPOINT_MAGIC: &[u8; 7] = b"RTCKPT\0";
+const CHECKPOINT_VERSION: u8 = 5;
+// the magic, the version, the size, the seed, the samples per pixel, the sampler and the filter
+const CHECKPOINT_HEADER_LEN: u64 = 7 + 1 + 4 + 4 + 8 + 4 + 1 + 1 + 8;
+// the sums, the luminance squares, the count, the weighted sums and the weight
+const CHECKPOINT_PIXEL_LEN: u64 = 3 * 8 + 8 + 4 + 3 * 8 + 8;
+
+// Dark pixels have their error measured against this luminance instead, or they would need
+// endless samples to bring a tiny noise down relative to their tiny value.
+const MIN_LUMINANCE: f64 = 0.01;
+
+// Below this filter weight per sample of the pixel, the negative lobes of a filter all but cancel
+// the weights out, and dividing by them would blow the pixel up or flip its sign.
+const MIN_FILTER_WEIGHT: f64 = 0.1;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "{}", e),
+            CheckpointError::Format(msg) => write!(f, "invalid checkpoint: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Io(e) => Some(e),
+            CheckpointError::Format(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+// New samples of a pixel.
+#[derive(Clone, Copy, Default)]
+pub struct PixelSamples {
+    pub sum: Color,
+    // for the variance
+    pub luminance_squares: f64,
+    pub count: usize,
+}
+
+impl PixelSamples {
+    pub fn add(&mut self, color: Color) {
+        self.sum += color;
+        self.luminance_squares += luminance(color).powi(2);
+        self.count += 1;
+    }
+}
+
+pub struct Film {
+    width: usize,
+    height: usize,
+    // the seed the samples were taken with, resuming with another one would repeat samples
+    seed: u64,
+    // resuming with another sampler or filter would mix samples that do not go together
+    sampler: SamplerKind,
+    filter: PixelFilter,
+    // the most samples a pixel takes, which the strata of the stratified sampler are made for
+    max_samples: usize,
+    // Per pixel, top row first. The samples taken for each pixel, which tell whether it needs
+    // more, and the filtered samples from it and its neighbors, which make up the image.
+    sums: Vec<Color>,
+    luminance_squares: Vec<f64>,
+    counts: Vec<u32>,
+    weighted_sums: Vec<Color>,
+    weights: Vec<f64>,
+}
+
+impl Film {
+    pub fn new(
+        width: usize,
+        height: usize,
+        seed: u64,
+        sampler: SamplerKind,
+        filter: PixelFilter,
+        max_samples: usize,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            seed,
+            sampler,
+            filter,
+            max_samples,
+            sums: vec![Color::default(); width * height],
+            luminance_squares: vec![0.0; width * height],
+            counts: vec![0; width * height],
+            weighted_sums: vec![Color::default(); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn sampler(&self) -> SamplerKind {
+        self.sampler
+    }
+
+    pub fn filter(&self) -> PixelFilter {
+        self.filter
+    }
+
+    pub fn max_samples(&self) -> usize {
+        self.max_samples
+    }
+
+    // Raises or lowers the samples a resumed render takes, for the samplers that allow it.
+    pub fn set_max_samples(&mut self, max_samples: usize) {
+        self.max_samples = max_samples;
+    }
+
+    // The number of samples taken for the pixel, also the index of its next sample.
+    pub fn count(&self, x: usize, y: usize) -> usize {
+        self.counts[y * self.width + x] as usize
+    }
+
+    pub fn add_samples(&mut self, x: usize, y: usize, samples: &PixelSamples) {
+        let i = y * self.width + x;
+        self.sums[i] += samples.sum;
+        self.luminance_squares[i] += samples.luminance_squares;
+        self.counts[i] += samples.count as u32;
+    }
+
+    // Adds a sample of the reconstruction filter, `weighted` being its color times `weight`.
+    pub fn add_splat(&mut self, x: usize, y: usize, weighted: Color, weight: f64) {
+        let i = y * self.width + x;
+        self.weighted_sums[i] += weighted;
+        self.weights[i] += weight;
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.counts.iter().map(|&c| c as u64).sum()
+    }
+
+    // The standard error of the mean luminance of the pixel, relative to that mean.
+    pub fn relative_error(&self, x: usize, y: usize) -> f64 {
+        let i = y * self.width + x;
+        let n = self.counts[i] as f64;
+        if n < 2.0 {
+            return f64::INFINITY;
+        }
+        let mean = luminance(self.sums[i]) / n;
+        // the unbiased sample variance, rounding can make it slightly negative
+        let variance = ((self.luminance_squares[i] / n - mean * mean) * n / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / mean.abs().max(MIN_LUMINANCE)
+    }
+
+    // The largest relative error in the 3x3 block around the pixel. The estimate of a single
+    // pixel is often wrong, for instance when none of its first samples happened to find a light.
+    pub fn local_error(&self, x: usize, y: usize) -> f64 {
+        let xs = x.saturating_sub(1)..=(x + 1).min(self.width - 1);
+        let ys = y.saturating_sub(1)..=(y + 1).min(self.height - 1);
+        ys.flat_map(|j| xs.clone().map(move |i| (i, j)))
+            .map(|(i, j)| self.relative_error(i, j))
+            .fold(0.0, f64::max)
+    }
+
+    // The filtered samples around each pixel, or the mean of its own samples where the filter
+    // weights nearly cancel out.
+    pub fn image(&self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                let count = self.counts[i] as f64;
+                if self.weights[i] > MIN_FILTER_WEIGHT * count.max(1.0) {
+                    image.set_pixel(x, y, self.weighted_sums[i] / self.weights[i]);
+                } else if count > 0.0 {
+                    image.set_pixel(x, y, self.sums[i] / count);
+                }
+            }
+        }
+        image
+    }
+
+    // The number of samples of each pixel, as gray levels where white is `max_samples`.
+    pub fn sample_count_image(&self, max_samples: usize) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let level = self.count(x, y) as f64 / max_samples.max(1) as f64;
+                image.set_pixel(x, y, Color::new(level, level, level));
+            }
+        }
+        image
+    }
+
+    // Writes a checkpoint, replacing the file only once it is complete so that an interrupted
+    // write does not lose the previous one.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+
+        let mut out = BufWriter::new(File::create(&partial)?);
+        out.write_all(CHECKPOINT_MAGIC)?;
+        out.write_all(&[CHECKPOINT_VERSION])?;
+        out.write_all(&(self.width as u32).to_le_bytes())?;
+        out.write_all(&(self.height as u32).to_le_bytes())?;
+        out.write_all(&self.seed.to_le_bytes())?;
+        out.write_all(&(self.max_samples as u32).to_le_bytes())?;
+        out.write_all(&[sampler_code(self.sampler), filter_code(self.filter.kind())])?;
+        out.write_all(&self.filter.radius().to_le_bytes())?;
+        for i in 0..self.sums.len() {
+            for c in 0..3 {
+                out.write_all(&self.sums[i][c].to_le_bytes())?;
+            }
+            out.write_all(&self.luminance_squares[i].to_le_bytes())?;
+            out.write_all(&self.counts[i].to_le_bytes())?;
+            for c in 0..3 {
+                out.write_all(&self.weighted_sums[i][c].to_le_bytes())?;
+            }
+            out.write_all(&self.weights[i].to_le_bytes())?;
+        }
+        out.into_inner()?.sync_all()?;
+        fs::rename(&partial, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Film, CheckpointError> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut input = BufReader::new(file);
+        let mut read = |buf: &mut [u8]| {
+            input.read_exact(buf).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => CheckpointError::Format("truncated file".into()),
+                _ => CheckpointError::Io(e),
+            })
+        };
+
+        let mut magic = [0; 7];
+        read(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(CheckpointError::Format("not a checkpoint file".into()));
+        }
+        let mut version = [0];
+        read(&mut version)?;
+        if version[0] != CHECKPOINT_VERSION {
+            return Err(CheckpointError::Format(format!(
+                "unsupported version {}",
+                version[0]
+            )));
+        }
+        let mut u32_buf = [0; 4];
+        let mut u64_buf = [0; 8];
+        read(&mut u32_buf)?;
+        let width = u32::from_le_bytes(u32_buf) as usize;
+        read(&mut u32_buf)?;
+        let height = u32::from_le_bytes(u32_buf) as usize;
+        read(&mut u64_buf)?;
+        let seed = u64::from_le_bytes(u64_buf);
+        read(&mut u32_buf)?;
+        let max_samples = u32::from_le_bytes(u32_buf) as usize;
+        let mut codes = [0; 2];
+        read(&mut codes)?;
+        read(&mut u64_buf)?;
+        let radius = f64::from_le_bytes(u64_buf);
+        let sampler = sampler_from_code(codes[0])
+            .ok_or_else(|| CheckpointError::Format(format!("unknown sampler {}", codes[0])))?;
+        let filter_kind = filter_from_code(codes[1])
+            .ok_or_else(|| CheckpointError::Format(format!("unknown filter {}", codes[1])))?;
+        if !(radius >= 0.5 && radius.is_finite()) {
+            return Err(CheckpointError::Format("invalid filter radius".into()));
+        }
+        if max_samples == 0 {
+            return Err(CheckpointError::Format("no samples per pixel".into()));
+        }
+
+        // check the size against the file before allocating the pixels
+        if width < 2 || height < 2 {
+            return Err(CheckpointError::Format(format!(
+                "invalid size {}x{}",
+                width, height
+            )));
+        }
+        let expected_len = (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|pixels| pixels.checked_mul(CHECKPOINT_PIXEL_LEN))
+            .and_then(|len| len.checked_add(CHECKPOINT_HEADER_LEN));
+        if expected_len != Some(file_len) {
+            return Err(CheckpointError::Format(format!(
+                "the file does not hold the {}x{} pixels of its header",
+                width, height
+            )));
+        }
+
+        let filter = PixelFilter::new(filter_kind, radius);
+        let mut film = Film::new(width, height, seed, sampler, filter, max_samples);
+        for i in 0..width * height {
+            let mut channels = [0.0; 3];
+            for c in &mut channels {
+                read(&mut u64_buf)?;
+                *c = f64::from_le_bytes(u64_buf);
+            }
+            read(&mut u64_buf)?;
+            film.luminance_squares[i] = f64::from_le_bytes(u64_buf);
+            read(&mut u32_buf)?;
+            film.sums[i] = Color::new(channels[0], channels[1], channels[2]);
+            film.counts[i] = u32::from_le_bytes(u32_buf);
+            for c in &mut channels {
+                read(&mut u64_buf)?;
+                *c = f64::from_le_bytes(u64_buf);
+            }
+            film.weighted_sums[i] = Color::new(channels[0], channels[1], channels[2]);
+            read(&mut u64_buf)?;
+            film.weights[i] = f64::from_le_bytes(u64_buf);
+        }
+        if read(&mut [0]).is_ok() {
+            return Err(CheckpointError::Format("trailing data".into()));
+        }
+        Ok(film)
+    }
+}
+
+fn sampler_code(sampler: SamplerKind) -> u8 {
+    match sampler {
+        SamplerKind::Independent => 0,
+        SamplerKind::Stratified => 1,
+        SamplerKind::Halton => 2,
+        SamplerKind::Sobol => 3,
+    }
+}
+
+fn sampler_from_code(code: u8) -> Option<SamplerKind> {
+    match code {
+        0 => Some(SamplerKind::Independent),
+        1 => Some(SamplerKind::Stratified),
+        2 => Some(SamplerKind::Halton),
+        3 => Some(SamplerKind::Sobol),
+        _ => None,
+    }
+}
+
+fn filter_code(kind: FilterKind) -> u8 {
+    match kind {
+        FilterKind::Box => 0,
+        FilterKind::Tent => 1,
+        FilterKind::Gaussian => 2,
+        FilterKind::Mitchell => 3,
+    }
+}
+
+fn filter_from_code(code: u8) -> Option<FilterKind> {
+    match code {
+        0 => Some(FilterKind::Box),
+        1 => Some(FilterKind::Tent),
+        2 => Some(FilterKind::Gaussian),
+        3 => Some(FilterKind::Mitchell),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::SolidBackground;
+    use crate::camera::Camera;
+    use crate::hit::HittableList;
+    use crate::integrator::PathIntegrator;
+    use crate::material::Lambertian;
+    use crate::renderer::{RenderSettings, Renderer};
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::vec3::Point3;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const SAMPLES: usize = 8;
+
+    fn temp_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raytracing-film-{}-{}", test, std::process::id()))
+    }
+
+    fn renderer(threads: usize, filter: PixelFilter) -> Renderer {
+        let material = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5))));
+        let world = Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material));
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            90.0,
+            1.5,
+            0.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        Renderer::new(
+            world,
+            HittableList::default(),
+            camera,
+            Arc::new(SolidBackground::new(0.7, 0.8, 1.0)),
+            Box::new(PathIntegrator::new(None, 3)),
+            RenderSettings {
+                threads,
+                max_samples: SAMPLES,
+                min_samples: SAMPLES,
+                error_threshold: None,
+                filter,
+                sampler: SamplerKind::Stratified,
+            },
+        )
+    }
+
+    fn film(filter: PixelFilter) -> Film {
+        Film::new(12, 8, 42, SamplerKind::Stratified, filter, SAMPLES)
+    }
+
+    // Checks that the films hold the same samples. Their sums may only differ by rounding, from
+    // adding them up in other passes.
+    fn assert_same(a: &Film, b: &Film) {
+        assert_eq!((a.width, a.height, a.seed), (b.width, b.height, b.seed));
+        assert_eq!((a.sampler, a.filter), (b.sampler, b.filter));
+        assert_eq!(a.max_samples, b.max_samples);
+        assert_eq!(a.counts, b.counts);
+        let close = |x: f64, y: f64| (x - y).abs() <= 1e-12 * x.abs().max(1.0);
+        let close_colors = |x: Color, y: Color| (0..3).all(|c| close(x[c], y[c]));
+        for i in 0..a.counts.len() {
+            assert!(close_colors(a.sums[i], b.sums[i]), "pixel {}", i);
+            assert!(
+                close_colors(a.weighted_sums[i], b.weighted_sums[i]),
+                "pixel {}",
+                i
+            );
+            assert!(
+                close(a.luminance_squares[i], b.luminance_squares[i]),
+                "pixel {}",
+                i
+            );
+            assert!(close(a.weights[i], b.weights[i]), "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let filter = PixelFilter::new(FilterKind::Mitchell, 2.0);
+        let mut saved = film(filter);
+        renderer(2, filter).render_pass(&mut saved, 3);
+        let path = temp_path("roundtrip");
+        saved.save(&path).unwrap();
+        let loaded = Film::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_same(&saved, &loaded.unwrap());
+    }
+
+    #[test]
+    fn resumed_render_matches_one_in_one_go() {
+        let filter = PixelFilter::new(FilterKind::Gaussian, 1.5);
+        let mut whole = film(filter);
+        renderer(1, filter).render_pass(&mut whole, SAMPLES);
+
+        let mut first = film(filter);
+        renderer(3, filter).render_pass(&mut first, 3);
+        let path = temp_path("resume");
+        first.save(&path).unwrap();
+        let mut resumed = Film::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let renderer = renderer(2, filter);
+        while renderer.render_pass(&mut resumed, 2).samples > 0 {}
+
+        assert_same(&whole, &resumed);
+    }
+
+    #[test]
+    fn filtered_image() {
+        let filter = PixelFilter::new(FilterKind::Mitchell, 2.0);
+        let mut film = Film::new(4, 1, 0, SamplerKind::Independent, filter, 4);
+        let mut samples = PixelSamples::default();
+        for _ in 0..4 {
+            samples.add(Color::new(0.2, 0.4, 0.6));
+        }
+        for x in 0..3 {
+            film.add_samples(x, 0, &samples);
+        }
+        let gray = Color::new(0.5, 0.5, 0.5);
+        // the weights, however large or negative, divide out of a constant color
+        film.add_splat(0, 0, gray * 2.5, 2.5);
+        film.add_splat(0, 0, gray * -0.3, -0.3);
+        film.add_splat(0, 0, gray * 0.8, 0.8);
+        // nearly cancelled out, under a tenth of a weight per sample, the pixel falls back to
+        // the mean of its own samples
+        film.add_splat(1, 0, gray * 0.6, 0.6);
+        film.add_splat(1, 0, gray * -0.3, -0.3);
+        film.add_splat(2, 0, gray * -0.5, -0.5);
+        // a pixel without samples of its own only needs the filter
+        film.add_splat(3, 0, gray * 0.2, 0.2);
+
+        let image = film.image();
+        let rgb = |x| {
+            let c = image.pixel(x, 0);
+            (c.x(), c.y(), c.z())
+        };
+        let close = |(r, g, b): (f64, f64, f64), (er, eg, eb): (f64, f64, f64)| {
+            (r - er).abs() < 1e-12 && (g - eg).abs() < 1e-12 && (b - eb).abs() < 1e-12
+        };
+        assert!(close(rgb(0), (0.5, 0.5, 0.5)), "{:?}", rgb(0));
+        assert!(close(rgb(1), (0.2, 0.4, 0.6)), "{:?}", rgb(1));
+        assert!(close(rgb(2), (0.2, 0.4, 0.6)), "{:?}", rgb(2));
+        assert!(close(rgb(3), (0.5, 0.5, 0.5)), "{:?}", rgb(3));
+    }
+
+    #[test]
+    fn malformed_checkpoints() {
+        let filter = PixelFilter::new(FilterKind::Box, 0.5);
+        let path = temp_path("malformed");
+        film(filter).save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let load = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            match Film::load(&path) {
+                Err(CheckpointError::Format(message)) => message,
+                Err(e) => panic!("expected a format error, got {}", e),
+                Ok(_) => panic!("expected a format error"),
+            }
+        };
+        assert_eq!(load(b"not a checkpoint"), "not a checkpoint file");
+        let mut old = bytes.clone();
+        old[7] = 4;
+        assert_eq!(load(&old), "unsupported version 4");
+        assert_eq!(
+            load(&bytes[..bytes.len() - 1]),
+            "the file does not hold the 12x8 pixels of its header"
+        );
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            load(&longer),
+            "the file does not hold the 12x8 pixels of its header"
+        );
+        // a huge size is refused before allocating anything
+        let mut huge = bytes.clone();
+        huge[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        huge[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            load(&huge),
+            "the file does not hold the 4294967295x4294967295 pixels of its header"
+        );
+        let mut no_samples = bytes.clone();
+        no_samples[24..28].copy_from_slice(&0_u32.to_le_bytes());
+        assert_eq!(load(&no_samples), "no samples per pixel");
+        let mut sampler = bytes;
+        sampler[28] = 9;
+        assert_eq!(load(&sampler), "unknown sampler 9");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+// Reconstruction filters, weighing how much a sample contributes to the pixels around it.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    // every sample counts for the pixel it falls in, and only for it with the default radius
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3, sharper, with small negative lobes
+    Mitchell,
+}
+
+impl FilterKind {
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+        }
+    }
+}
+
+// The falloff of the Gaussian, for a standard deviation of half a pixel.
+const GAUSSIAN_ALPHA: f64 = 2.0;
+const MITCHELL_B: f64 = 1.0 / 3.0;
+const MITCHELL_C: f64 = 1.0 / 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelFilter {
+    kind: FilterKind,
+    // in pixels, the filter is zero beyond it on either axis
+    radius: f64,
+}
+
+impl PixelFilter {
+    pub fn new(kind: FilterKind, radius: f64) -> Self {
+        Self { kind, radius }
+    }
+
+    pub fn kind(&self) -> FilterKind {
+        self.kind
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // The weight of a sample at the offset (dx, dy) from the center of a pixel.
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, d: f64) -> f64 {
+        let d = d.abs();
+        if d >= self.radius {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => self.radius - d,
+            FilterKind::Gaussian => {
+                // shifted down to reach zero at the radius
+                (-GAUSSIAN_ALPHA * d * d).exp()
+                    - (-GAUSSIAN_ALPHA * self.radius * self.radius).exp()
+            }
+            FilterKind::Mitchell => mitchell(2.0 * d / self.radius),
+        }
+    }
+}
+
+// The Mitchell-Netravali cubic, over [0, 2).
+fn mitchell(x: f64) -> f64 {
+    let (b, c) = (MITCHELL_B, MITCHELL_C);
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b)
+    } else {
+        (-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    };
+    value / 6.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 4] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+    ];
+
+    // The integral of the filter along one axis, by the midpoint rule.
+    fn integral(filter: &PixelFilter) -> f64 {
+        let n = 100_000;
+        let step = 2.0 * filter.radius() / n as f64;
+        (0..n)
+            .map(|i| filter.weight_1d(-filter.radius() + (i as f64 + 0.5) * step) * step)
+            .sum()
+    }
+
+    #[test]
+    fn footprint() {
+        for kind in KINDS {
+            for radius in [kind.default_radius(), 0.75, 3.0] {
+                let filter = PixelFilter::new(kind, radius);
+                for d in [radius, radius + 0.01, 2.0 * radius, 100.0] {
+                    assert_eq!(filter.weight(d, 0.0), 0.0, "{:?} {} at {}", kind, radius, d);
+                    assert_eq!(
+                        filter.weight(0.0, -d),
+                        0.0,
+                        "{:?} {} at {}",
+                        kind,
+                        radius,
+                        d
+                    );
+                    assert_eq!(filter.weight(-d, d), 0.0, "{:?} {} at {}", kind, radius, d);
+                }
+                // largest at the center, and reaching out to nearly the radius
+                let center = filter.weight_1d(0.0);
+                assert!(center > 0.0);
+                for d in [0.1, 0.5, 0.9, 0.99] {
+                    let w = filter.weight_1d(d * radius);
+                    assert!(
+                        w != 0.0 && w.abs() <= center,
+                        "{:?} {} at {}",
+                        kind,
+                        radius,
+                        d
+                    );
+                    assert_eq!(w, filter.weight_1d(-d * radius));
+                    assert_eq!(filter.weight(d * radius, 0.3), w * filter.weight_1d(0.3));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_mitchell_goes_negative() {
+        for kind in KINDS {
+            let filter = PixelFilter::new(kind, kind.default_radius());
+            let lowest = (0..1000)
+                .map(|i| filter.weight_1d(i as f64 / 1000.0 * filter.radius()))
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(lowest < 0.0, kind == FilterKind::Mitchell, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn normalization() {
+        // the box and the tent cover their footprint evenly
+        assert!((integral(&PixelFilter::new(FilterKind::Box, 0.5)) - 1.0).abs() < 1e-9);
+        assert!((integral(&PixelFilter::new(FilterKind::Tent, 1.0)) - 1.0).abs() < 1e-9);
+        assert!((integral(&PixelFilter::new(FilterKind::Tent, 2.0)) - 4.0).abs() < 1e-6);
+
+        // the Mitchell-Netravali cubic integrates to one over [-2, 2], and stretches with its
+        // radius; it is continuous where its pieces meet, and reaches zero at the radius
+        for radius in [2.0, 1.0, 3.0] {
+            let filter = PixelFilter::new(FilterKind::Mitchell, radius);
+            assert!(
+                (integral(&filter) - radius / 2.0).abs() < 1e-6,
+                "{}",
+                radius
+            );
+        }
+        assert!((mitchell(0.0) - 8.0 / 9.0).abs() < 1e-12);
+        assert!((mitchell(1.0 - 1e-12) - mitchell(1.0)).abs() < 1e-9);
+        assert!(mitchell(2.0).abs() < 1e-12);
+
+        // the Gaussian, shifted down, is positive up to its radius and nearly zero next to it
+        let filter = PixelFilter::new(FilterKind::Gaussian, 1.5);
+        assert!(filter.weight_1d(1.5 - 1e-9).abs() < 1e-9);
+        // the tails of the Gaussian beyond the radius hold a little under 0.3% of it
+        let unshifted = (std::f64::consts::PI / GAUSSIAN_ALPHA).sqrt();
+        let shift = 3.0 * (-GAUSSIAN_ALPHA * 1.5 * 1.5).exp();
+        let shifted = integral(&filter);
+        assert!(shifted < unshifted - shift && shifted > 0.997 * unshifted - shift);
+    }
+}
@@ -1,10 +1,14 @@
 use std::ops::RangeInclusive;
 use std::sync::Arc;
 
+use rand::rngs::SmallRng;
+
 use crate::aabb::AABB;
 use crate::bvh::BVH;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::util::dot;
 use crate::vec3::{Point3, Vec3};
 
 pub enum Normal {
@@ -33,8 +37,43 @@ impl HitRecord {
 }
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, ray: &Ray, t_range: &RangeInclusive<f64>) -> Option<HitRecord>;
+    // `sampler` is for objects hit at random, such as participating media.
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord>;
     fn bounding_box(&self) -> Option<AABB>;
+
+    // The surface area of the objects that can be sampled as lights, `None` for the others, such
+    // as media. It weighs an object against the others it is picked among.
+    fn area(&self) -> Option<f64> {
+        None
+    }
+
+    // The density, over the solid angle seen from `origin`, of the directions `random` picks
+    // from there towards the object, zero for the objects without an area.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _sampler: &mut dyn Sampler) -> f64 {
+        0.0
+    }
+
+    // Only the objects with an area are sampled as lights.
+    fn random(&self, _origin: &Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        unreachable!("sampling an object without an area")
+    }
+}
+
+// The density over solid angle of a direction meeting a surface at `t` along it, for points
+// picked uniformly over `area` of the surface.
+pub fn area_pdf(t: f64, direction: &Vec3, normal: &Vec3, area: f64) -> f64 {
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = (dot(direction, normal) / direction.length()).abs();
+    // seen edge on, the surface is never hit
+    if cosine < 1E-8 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
 }
 
 #[derive(Default)]
@@ -47,17 +86,26 @@ impl HittableList {
         self.objects.push(obj);
     }
 
-    pub fn into_bvh(self) -> BVH {
-        BVH::new(&self.objects)
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn into_bvh(self, rng: &mut SmallRng) -> BVH {
+        BVH::new(&self.objects, rng)
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_range: &RangeInclusive<f64>) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
         let mut hit_record = None;
         let mut range = t_range.clone();
         for obj in &self.objects {
-            if let Some(hit) = obj.hit(ray, &range) {
+            if let Some(hit) = obj.hit(ray, &range, sampler) {
                 range = RangeInclusive::new(*range.start(), hit.t);
                 hit_record = Some(hit);
             }
@@ -84,4 +132,73 @@ impl Hittable for HittableList {
         }
         output_box
     }
+
+    fn area(&self) -> Option<f64> {
+        if self.objects.is_empty() {
+            return None;
+        }
+        self.objects.iter().map(|obj| obj.area()).sum()
+    }
+
+    // Picks one of the objects uniformly, the density is the average of theirs.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|obj| obj.pdf_value(origin, direction, sampler))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let n = self.objects.len();
+        let i = ((sampler.get_1d() * n as f64) as usize).min(n - 1);
+        self.objects[i].random(origin, sampler)
+    }
+}
+
+// Swaps the front and back faces of an object, turning its outward normals inward.
+pub struct FlipFace {
+    object: Arc<dyn Hittable>,
+}
+
+impl FlipFace {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
+        Self { object }
+    }
+}
+
+impl Hittable for FlipFace {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let mut hit = self.object.hit(ray, t_range, sampler)?;
+        hit.normal = match hit.normal {
+            Normal::Front(n) => Normal::Back(n),
+            Normal::Back(n) => Normal::Front(n),
+        };
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.object.bounding_box()
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.object.area()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.object.pdf_value(origin, direction, sampler)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random(origin, sampler)
+    }
 }
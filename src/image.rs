@@ -0,0 +1,805 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::vec3::Color;
+use crate::zlib;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::Format(msg) => write!(f, "invalid image: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+fn format_error<T>(msg: &str) -> Result<T, ImageError> {
+    Err(ImageError::Format(msg.to_owned()))
+}
+
+// The most pixels a byte of Radiance HDR data holds: about 16 with the run length encoding of
+// each channel, more only with the rare runs of the old encoding.
+const HDR_MAX_PIXELS_PER_BYTE: usize = 64;
+
+// Checks the size read from a header before allocating the image. It must not be empty, nor have
+// more than `max_pixels`, as many as the rest of the data can hold.
+fn check_size(width: usize, height: usize, max_pixels: usize) -> Result<(), ImageError> {
+    if width == 0 || height == 0 {
+        return format_error("empty image");
+    }
+    match width.checked_mul(height) {
+        Some(pixels) if pixels <= max_pixels => Ok(()),
+        _ => format_error("the image is larger than its data"),
+    }
+}
+
+// Converts an sRGB encoded component to linear light.
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+// How texture coordinates outside of [0, 1] are mapped back onto the image.
+#[derive(Clone, Copy)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+}
+
+impl Wrap {
+    fn apply(self, i: i64, len: usize) -> usize {
+        match self {
+            Wrap::Clamp => i.clamp(0, len as i64 - 1) as usize,
+            Wrap::Repeat => i.rem_euclid(len as i64) as usize,
+        }
+    }
+}
+
+// A bitmap of linear colors, stored row by row from the top-left corner.
+#[derive(Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // Samples the image at the texture coordinates (u, v), where v goes from the bottom row up.
+    pub fn sample(&self, u: f64, v: f64, filter: Filter, wrap: Wrap) -> Color {
+        let x = u * self.width as f64;
+        let y = (1.0 - v) * self.height as f64;
+        match filter {
+            Filter::Nearest => self.pixel(
+                wrap.apply(x.floor() as i64, self.width),
+                wrap.apply(y.floor() as i64, self.height),
+            ),
+            Filter::Bilinear => {
+                // pixel centers are at half-integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let (x1, y1) = (
+                    wrap.apply(x0 + 1, self.width),
+                    wrap.apply(y0 + 1, self.height),
+                );
+                let (x0, y0) = (wrap.apply(x0, self.width), wrap.apply(y0, self.height));
+
+                let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x1, y0) * fx;
+                let bottom = self.pixel(x0, y1) * (1.0 - fx) + self.pixel(x1, y1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+
+    // Loads an image, picking the format by its extension. Low dynamic range formats are
+    // assumed to be sRGB encoded and are converted to linear colors.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("hdr") | Some("pic") => Image::load_hdr(path),
+            Some("ppm") | Some("pnm") => Ok(Image::load_ppm(path)?.srgb_to_linear()),
+            Some("png") => Ok(Image::load_png(path)?.srgb_to_linear()),
+            _ => format_error(&format!("unsupported image format {}", path.display())),
+        }
+    }
+
+    // Loads a Radiance HDR (RGBE) file.
+    pub fn load_hdr<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+        decode_hdr(&fs::read(path)?)
+    }
+
+    // Loads an ASCII (P3) or binary (P6) PPM file, with components in [0, 1] as stored.
+    pub fn load_ppm<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+        decode_ppm(&fs::read(path)?)
+    }
+
+    // Loads a PNG file, with components in [0, 1] as stored.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+        decode_png(&fs::read(path)?)
+    }
+
+    pub fn srgb_to_linear(mut self) -> Self {
+        for p in &mut self.pixels {
+            *p = Color::new(
+                srgb_to_linear(p.x()),
+                srgb_to_linear(p.y()),
+                srgb_to_linear(p.z()),
+            );
+        }
+        self
+    }
+}
+
+fn read_line<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a str, ImageError> {
+    let start = *pos;
+    while *pos < data.len() && data[*pos] != b'\n' {
+        *pos += 1;
+    }
+    if *pos == data.len() {
+        return format_error("unexpected end of header");
+    }
+    *pos += 1;
+    std::str::from_utf8(&data[start..*pos - 1])
+        .or_else(|_| format_error("header is not valid text"))
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::default();
+    }
+    let f = 2.0_f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * f,
+        (rgbe[1] as f64 + 0.5) * f,
+        (rgbe[2] as f64 + 0.5) * f,
+    )
+}
+
+pub(crate) fn decode_hdr(data: &[u8]) -> Result<Image, ImageError> {
+    let mut pos = 0;
+    let magic = read_line(data, &mut pos)?;
+    if !magic.starts_with("#?") {
+        return format_error("missing Radiance signature");
+    }
+    loop {
+        let line = read_line(data, &mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return format_error(&format!("unsupported pixel format {}", format));
+            }
+        }
+    }
+
+    // Only the standard orientation, top to bottom and left to right, is supported.
+    let resolution: Vec<&str> = read_line(data, &mut pos)?.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", h, "+X", w] => match (h.parse::<usize>(), w.parse::<usize>()) {
+            (Ok(h), Ok(w)) => (h, w),
+            _ => return format_error("invalid resolution"),
+        },
+        _ => return format_error("unsupported resolution line"),
+    };
+    check_size(
+        width,
+        height,
+        (data.len() - pos).saturating_mul(HDR_MAX_PIXELS_PER_BYTE),
+    )?;
+
+    let mut image = Image::new(width, height);
+    let mut scanline = vec![[0_u8; 4]; width];
+    for y in 0..height {
+        read_hdr_scanline(data, &mut pos, &mut scanline)?;
+        for (x, rgbe) in scanline.iter().enumerate() {
+            image.set_pixel(x, y, rgbe_to_color(*rgbe));
+        }
+    }
+    Ok(image)
+}
+
+fn next_byte(data: &[u8], pos: &mut usize) -> Result<u8, ImageError> {
+    match data.get(*pos) {
+        Some(b) => {
+            *pos += 1;
+            Ok(*b)
+        }
+        None => format_error("unexpected end of pixel data"),
+    }
+}
+
+fn read_hdr_scanline(
+    data: &[u8],
+    pos: &mut usize,
+    scanline: &mut [[u8; 4]],
+) -> Result<(), ImageError> {
+    let width = scanline.len();
+    let new_rle = (8..0x8000).contains(&width)
+        && data.len() >= *pos + 4
+        && data[*pos] == 2
+        && data[*pos + 1] == 2
+        && data[*pos + 2] & 0x80 == 0;
+    if new_rle {
+        let encoded_width = ((data[*pos + 2] as usize) << 8) | data[*pos + 3] as usize;
+        if encoded_width != width {
+            return format_error("scanline width mismatch");
+        }
+        *pos += 4;
+
+        // Each channel is stored separately, as a sequence of runs and literal spans.
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = next_byte(data, pos)? as usize;
+                if count > 128 {
+                    let count = count - 128;
+                    if x + count > width {
+                        return format_error("run overflows scanline");
+                    }
+                    let value = next_byte(data, pos)?;
+                    for pixel in &mut scanline[x..x + count] {
+                        pixel[channel] = value;
+                    }
+                    x += count;
+                } else {
+                    if count == 0 || x + count > width {
+                        return format_error("invalid literal span");
+                    }
+                    for pixel in &mut scanline[x..x + count] {
+                        pixel[channel] = next_byte(data, pos)?;
+                    }
+                    x += count;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // Flat pixels, possibly using the old run length encoding where a (1, 1, 1, n) pixel
+    // repeats the previous one.
+    let mut x = 0;
+    let mut shift = 0;
+    while x < width {
+        let rgbe = [
+            next_byte(data, pos)?,
+            next_byte(data, pos)?,
+            next_byte(data, pos)?,
+            next_byte(data, pos)?,
+        ];
+        if rgbe[0] == 1 && rgbe[1] == 1 && rgbe[2] == 1 {
+            if x == 0 {
+                return format_error("run at start of scanline");
+            }
+            if rgbe[3] == 0 {
+                return format_error("empty run");
+            }
+            // the count is shifted by 8 more bits for every run in a row
+            if shift >= usize::BITS || rgbe[3] as usize > (width - x) >> shift {
+                return format_error("run overflows scanline");
+            }
+            let count = (rgbe[3] as usize) << shift;
+            let previous = scanline[x - 1];
+            for pixel in &mut scanline[x..x + count] {
+                *pixel = previous;
+            }
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = rgbe;
+            x += 1;
+            shift = 0;
+        }
+    }
+    Ok(())
+}
+
+// Reads the next whitespace separated token of a PPM header, skipping comments.
+fn ppm_token<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a str, ImageError> {
+    loop {
+        while *pos < data.len() && data[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < data.len() && data[*pos] == b'#' {
+            while *pos < data.len() && data[*pos] != b'\n' {
+                *pos += 1;
+            }
+            continue;
+        }
+        break;
+    }
+    let start = *pos;
+    while *pos < data.len() && !data[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return format_error("unexpected end of PPM data");
+    }
+    std::str::from_utf8(&data[start..*pos]).or_else(|_| format_error("invalid PPM header"))
+}
+
+fn ppm_number(data: &[u8], pos: &mut usize) -> Result<usize, ImageError> {
+    ppm_token(data, pos)?
+        .parse()
+        .or_else(|_| format_error("invalid number in PPM data"))
+}
+
+pub(crate) fn decode_ppm(data: &[u8]) -> Result<Image, ImageError> {
+    let mut pos = 0;
+    let magic = ppm_token(data, &mut pos)?;
+    let binary = match magic {
+        "P3" => false,
+        "P6" => true,
+        _ => return format_error(&format!("unsupported PPM type {}", magic)),
+    };
+    let width = ppm_number(data, &mut pos)?;
+    let height = ppm_number(data, &mut pos)?;
+    let max_value = ppm_number(data, &mut pos)?;
+    if max_value == 0 || max_value > 65535 {
+        return format_error("invalid PPM maximum value");
+    }
+    let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+    let remaining = data.len().saturating_sub(pos + 1);
+    let max_pixels = if binary {
+        remaining / (3 * bytes_per_sample)
+    } else {
+        // three numbers per pixel, each at least a digit and a separator
+        (remaining + 1) / 6
+    };
+    check_size(width, height, max_pixels)?;
+
+    let mut image = Image::new(width, height);
+    let mut components = Vec::with_capacity(width * height * 3);
+    if binary {
+        // a single whitespace character separates the header from the raster
+        pos += 1;
+        let raster = data
+            .get(pos..pos + width * height * 3 * bytes_per_sample)
+            .ok_or_else(|| ImageError::Format("truncated PPM raster".to_owned()))?;
+        if bytes_per_sample == 1 {
+            components.extend(raster.iter().map(|&b| b as usize));
+        } else {
+            components.extend(
+                raster
+                    .chunks(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize),
+            );
+        }
+    } else {
+        for _ in 0..width * height * 3 {
+            components.push(ppm_number(data, &mut pos)?);
+        }
+    }
+
+    for (i, rgb) in components.chunks(3).enumerate() {
+        let scale = 1.0 / max_value as f64;
+        image.set_pixel(
+            i % width,
+            i / width,
+            Color::new(
+                rgb[0] as f64 * scale,
+                rgb[1] as f64 * scale,
+                rgb[2] as f64 * scale,
+            ),
+        );
+    }
+    Ok(image)
+}
+
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+// (x offset, y offset, x step, y step) of the seven Adam7 interlacing passes
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+pub(crate) fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Reverses the per-scanline filters of a (sub-)image, returning the raw rows.
+fn png_unfilter(
+    data: &[u8],
+    rows: usize,
+    row_len: usize,
+    bpp: usize,
+) -> Result<Vec<u8>, ImageError> {
+    let mut out = vec![0_u8; rows * row_len];
+    for y in 0..rows {
+        let filter = data[y * (row_len + 1)];
+        let line = &data[y * (row_len + 1) + 1..(y + 1) * (row_len + 1)];
+        for x in 0..row_len {
+            let a = if x >= bpp {
+                out[y * row_len + x - bpp]
+            } else {
+                0
+            };
+            let b = if y > 0 { out[(y - 1) * row_len + x] } else { 0 };
+            let c = if x >= bpp && y > 0 {
+                out[(y - 1) * row_len + x - bpp]
+            } else {
+                0
+            };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return format_error("invalid PNG filter type"),
+            };
+            out[y * row_len + x] = line[x].wrapping_add(predictor);
+        }
+    }
+    Ok(out)
+}
+
+pub(crate) fn decode_png(data: &[u8]) -> Result<Image, ImageError> {
+    if data.len() < 8 || data[..8] != PNG_SIGNATURE {
+        return format_error("missing PNG signature");
+    }
+
+    let mut pos = 8;
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = vec![];
+    loop {
+        if pos + 8 > data.len() {
+            return format_error("truncated PNG chunk");
+        }
+        let len =
+            u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let body = data
+            .get(pos + 8..pos + 8 + len)
+            .ok_or_else(|| ImageError::Format("truncated PNG chunk".to_owned()))?;
+        // skip the body and the CRC
+        pos += 12 + len;
+        match kind {
+            b"IHDR" => {
+                if len != 13 {
+                    return format_error("invalid IHDR chunk");
+                }
+                let width = u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize;
+                let height = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+                // (width, height, bit depth, color type, interlaced)
+                header = Some((width, height, body[8], body[9], body[12] == 1));
+            }
+            b"PLTE" => palette = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let (width, height, bit_depth, color_type, interlaced) =
+        header.ok_or_else(|| ImageError::Format("missing IHDR chunk".to_owned()))?;
+    let channels = match (color_type, bit_depth) {
+        (0, 1) | (0, 2) | (0, 4) | (0, 8) | (0, 16) => 1,
+        (2, 8) | (2, 16) => 3,
+        (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+        (4, 8) | (4, 16) => 2,
+        (6, 8) | (6, 16) => 4,
+        _ => return format_error("unsupported PNG color type or bit depth"),
+    };
+    let bits_per_pixel = channels * bit_depth as usize;
+    let bpp = bits_per_pixel.div_ceil(8);
+    let raw = zlib::decompress(&compressed).map_err(ImageError::Format)?;
+    check_size(width, height, raw.len().saturating_mul(8) / bits_per_pixel)?;
+
+    let passes: Vec<(usize, usize, usize, usize)> = if interlaced {
+        ADAM7.to_vec()
+    } else {
+        vec![(0, 0, 1, 1)]
+    };
+    let max_value = ((1_u32 << bit_depth) - 1) as f64;
+    let mut image = Image::new(width, height);
+    let mut offset = 0;
+    for (x0, y0, dx, dy) in passes {
+        let pass_width = (width + dx - 1 - x0) / dx;
+        let pass_height = (height + dy - 1 - y0) / dy;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let row_len = (pass_width * bits_per_pixel).div_ceil(8);
+        let size = pass_height * (row_len + 1);
+        let filtered = raw
+            .get(offset..offset + size)
+            .ok_or_else(|| ImageError::Format("truncated PNG image data".to_owned()))?;
+        offset += size;
+        let rows = png_unfilter(filtered, pass_height, row_len, bpp)?;
+
+        for py in 0..pass_height {
+            let row = &rows[py * row_len..(py + 1) * row_len];
+            // the value of the `i`th sample on this row
+            let sample = |i: usize| -> u32 {
+                match bit_depth {
+                    16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]) as u32,
+                    8 => row[i] as u32,
+                    _ => {
+                        let bit = i * bit_depth as usize;
+                        let shift = 8 - bit_depth as usize - bit % 8;
+                        ((row[bit / 8] >> shift) & ((1 << bit_depth) - 1)) as u32
+                    }
+                }
+            };
+            for px in 0..pass_width {
+                let color = match color_type {
+                    3 => {
+                        let idx = sample(px) as usize;
+                        let rgb = palette.get(idx * 3..idx * 3 + 3).ok_or_else(|| {
+                            ImageError::Format("PNG palette index out of range".to_owned())
+                        })?;
+                        Color::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64) / 255.0
+                    }
+                    // gray, with or without alpha
+                    0 | 4 => {
+                        let gray = sample(px * channels) as f64 / max_value;
+                        Color::new(gray, gray, gray)
+                    }
+                    // alpha, if any, is ignored
+                    _ => {
+                        Color::new(
+                            sample(px * channels) as f64,
+                            sample(px * channels + 1) as f64,
+                            sample(px * channels + 2) as f64,
+                        ) / max_value
+                    }
+                };
+                image.set_pixel(x0 + px * dx, y0 + py * dy, color);
+            }
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_message(result: Result<Image, ImageError>) -> String {
+        match result {
+            Err(ImageError::Format(message)) => message,
+            Err(e) => panic!("expected a format error, got {}", e),
+            Ok(_) => panic!("expected a format error"),
+        }
+    }
+
+    fn rgb(c: Color) -> (f64, f64, f64) {
+        (c.x(), c.y(), c.z())
+    }
+
+    // Builds a PNG from the IHDR fields and the filtered scanlines; the CRCs are not checked.
+    fn png(width: u32, height: u32, depth: u8, color_type: u8, rows: &[u8]) -> Vec<u8> {
+        let mut ihdr = vec![];
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+
+        let mut data = PNG_SIGNATURE.to_vec();
+        for (kind, body) in [
+            (b"IHDR", ihdr),
+            (b"IDAT", zlib::compress(rows)),
+            (b"IEND", vec![]),
+        ] {
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(kind);
+            data.extend_from_slice(&body);
+            data.extend_from_slice(&[0; 4]);
+        }
+        data
+    }
+
+    #[test]
+    fn ascii_ppm() {
+        let image = decode_ppm(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 255\n").unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(rgb(image.pixel(0, 0)), (1.0, 0.0, 0.0));
+        assert_eq!(rgb(image.pixel(1, 0)), (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn binary_ppm() {
+        let image = decode_ppm(b"P6 1 2 255\n\xff\x00\x00\x00\xff\x00").unwrap();
+        assert_eq!((image.width(), image.height()), (1, 2));
+        assert_eq!(rgb(image.pixel(0, 1)), (0.0, 1.0, 0.0));
+
+        let image = decode_ppm(b"P6 1 1 65535\n\xff\xff\x00\x00\x00\x00").unwrap();
+        assert_eq!(rgb(image.pixel(0, 0)), (1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn malformed_ppm() {
+        assert_eq!(format_message(decode_ppm(b"P6 0 0 255\n")), "empty image");
+        assert_eq!(
+            format_message(decode_ppm(b"P6 100000 100000 255\n\0\0\0")),
+            "the image is larger than its data"
+        );
+        assert_eq!(
+            format_message(decode_ppm(b"P3 99999999999 99999999999 255\n0 0 0\n")),
+            "the image is larger than its data"
+        );
+        assert!(decode_ppm(b"P6 2 1 255\n\0\0\0\0\0").is_err());
+        assert!(decode_ppm(b"P3 1 1 255\n0 0\n").is_err());
+        assert!(decode_ppm(b"P6 1 1 0\n\0\0\0").is_err());
+        assert!(decode_ppm(b"P5 1 1 255\n\0").is_err());
+    }
+
+    #[test]
+    fn png_rgb_and_gray() {
+        // RGB, the second row using the Up filter
+        let rows = [0, 255, 0, 0, 0, 0, 255, 2, 0, 255, 0, 0, 0, 0];
+        let image = decode_png(&png(2, 2, 8, 2, &rows)).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(rgb(image.pixel(0, 0)), (1.0, 0.0, 0.0));
+        assert_eq!(rgb(image.pixel(1, 0)), (0.0, 0.0, 1.0));
+        assert_eq!(rgb(image.pixel(0, 1)), (1.0, 1.0, 0.0));
+        assert_eq!(rgb(image.pixel(1, 1)), (0.0, 0.0, 1.0));
+
+        // 1-bit gray
+        let image = decode_png(&png(3, 1, 1, 0, &[0, 0b1010_0000])).unwrap();
+        assert_eq!(rgb(image.pixel(0, 0)), (1.0, 1.0, 1.0));
+        assert_eq!(rgb(image.pixel(1, 0)), (0.0, 0.0, 0.0));
+        assert_eq!(rgb(image.pixel(2, 0)), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn malformed_png() {
+        assert_eq!(
+            format_message(decode_png(&png(0, 0, 8, 2, &[]))),
+            "empty image"
+        );
+        assert_eq!(
+            format_message(decode_png(&png(u32::MAX, u32::MAX, 8, 2, &[0; 64]))),
+            "the image is larger than its data"
+        );
+        assert!(decode_png(&png(2, 2, 8, 2, &[0; 7])).is_err());
+        assert!(decode_png(&png(1, 1, 8, 2, &[5, 0, 0, 0])).is_err());
+        assert!(decode_png(&png(1, 1, 3, 2, &[0, 0, 0, 0])).is_err());
+        // no palette
+        assert!(decode_png(&png(1, 1, 8, 3, &[0, 0])).is_err());
+        let mut truncated = png(1, 1, 8, 2, &[0, 0, 0, 0]);
+        truncated.truncate(30);
+        assert!(decode_png(&truncated).is_err());
+        assert!(decode_png(b"not a png").is_err());
+    }
+
+    #[test]
+    fn hdr_flat_and_runs() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 3\n".to_vec();
+        // a flat pixel, followed by an old style run repeating it twice
+        data.extend_from_slice(&[128, 64, 0, 129, 1, 1, 1, 2]);
+        let image = decode_hdr(&data).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 1));
+        for x in 0..3 {
+            let c = image.pixel(x, 0);
+            assert!((c.x() - 1.00390625).abs() < 1e-12);
+            assert!((c.y() - 0.50390625).abs() < 1e-12);
+        }
+
+        // runs in a row: 1 + 43 + (1 << 8) pixels
+        let mut data = b"#?RADIANCE\n\n-Y 1 +X 300\n".to_vec();
+        data.extend_from_slice(&[128, 64, 0, 129, 1, 1, 1, 43, 1, 1, 1, 1]);
+        let image = decode_hdr(&data).unwrap();
+        assert!((image.pixel(299, 0).x() - 1.00390625).abs() < 1e-12);
+    }
+
+    #[test]
+    fn malformed_hdr() {
+        let header = b"#?RADIANCE\n\n".to_vec();
+        let hdr = |resolution: &str, pixels: &[u8]| {
+            let mut data = header.clone();
+            data.extend_from_slice(resolution.as_bytes());
+            data.extend_from_slice(pixels);
+            decode_hdr(&data)
+        };
+        assert_eq!(format_message(hdr("-Y 0 +X 0\n", &[])), "empty image");
+        assert_eq!(
+            format_message(hdr("-Y 1000000 +X 1000000\n", &[0; 16])),
+            "the image is larger than its data"
+        );
+        assert!(hdr("-Y 1 +X 2\n", &[1, 2, 3, 4]).is_err());
+        assert!(hdr("+Y 1 +X 1\n", &[1, 2, 3, 4]).is_err());
+        assert!(hdr("-Y 1 +X 1\n", &[1, 1, 1, 1]).is_err());
+
+        // old style runs: empty, too long, and so many in a row that their shift overflows
+        let run = |count: u8, runs: usize| {
+            let mut pixels = vec![5, 5, 5, 130];
+            for _ in 0..runs {
+                pixels.extend_from_slice(&[1, 1, 1, count]);
+            }
+            pixels
+        };
+        assert_eq!(format_message(hdr("-Y 1 +X 4\n", &run(0, 1))), "empty run");
+        assert_eq!(
+            format_message(hdr("-Y 1 +X 4\n", &run(4, 1))),
+            "run overflows scanline"
+        );
+        assert_eq!(
+            format_message(hdr("-Y 1 +X 4\n", &run(1, 10))),
+            "run overflows scanline"
+        );
+        assert_eq!(
+            format_message(hdr("-Y 1 +X 2000\n", &run(255, 10))),
+            "run overflows scanline"
+        );
+        assert!(decode_hdr(b"P6\n").is_err());
+    }
+}
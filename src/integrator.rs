@@ -0,0 +1,547 @@
+// What a camera ray brings back: the light arriving along it, found in one way or another, or
+// some property of what it hits, to look at the scene with or debug it.
+
+use std::ops::RangeInclusive;
+
+use crate::background::Background;
+use crate::bvh;
+use crate::hit::{HitRecord, Hittable, HittableList, Normal};
+use crate::material::ScatterRecord;
+use crate::pdf::{CosinePdf, HittablePdf, Pdf};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Color;
+
+// The bounces through mirrors and glass the direct lighting integrator follows by default.
+pub const DEFAULT_SPECULAR_DEPTH: usize = 16;
+
+// The BVH nodes a ray visits for the traversal cost to show as hot.
+const BVH_COST_SCALE: f64 = 128.0;
+
+// What the integrators trace rays against.
+pub struct SceneView<'a> {
+    pub world: &'a dyn Hittable,
+    // sampled directly at diffuse bounces
+    pub lights: &'a HittableList,
+    pub background: &'a dyn Background,
+}
+
+impl SceneView<'_> {
+    fn hit(&self, ray: &Ray, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        self.world
+            .hit(ray, &RangeInclusive::new(0.001, f64::INFINITY), sampler)
+    }
+}
+
+pub trait Integrator: Send + Sync {
+    // The color of a camera ray, counting the rays it traces into `rays`.
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntegratorKind {
+    Path,
+    Direct,
+    AmbientOcclusion,
+    Normals,
+    Uv,
+    Depth,
+    BvhCost,
+}
+
+// Follows the path the ray starts bounce after bounce, adding up the light found along it, scaled
+// by the throughput: the fraction of that light the bounces so far pass on to the camera.
+//
+// Light is found both by sampling the lights at each diffuse bounce and by the scattered rays
+// hitting them, and the two are weighed by multiple importance sampling. Paths end once they reach
+// `max_depth` bounces, if any, and may end by Russian roulette after `roulette_depth`.
+pub struct PathIntegrator {
+    max_depth: Option<usize>,
+    roulette_depth: usize,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: Option<usize>, roulette_depth: usize) -> Self {
+        Self {
+            max_depth,
+            roulette_depth,
+        }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(
+        &self,
+        mut ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        // the density with which a diffuse bounce picked the ray, `None` for camera rays and those
+        // of mirrors and glass
+        let mut scattering_pdf = None;
+        let mut depth = 0;
+
+        while self.max_depth.is_none_or(|max| depth < max) {
+            *rays += 1;
+            let hit = match scene.hit(&ray, sampler) {
+                Some(hit) => hit,
+                None => {
+                    color += throughput * scene.background.value(&ray);
+                    break;
+                }
+            };
+            let mut emitted = hit.material.emitted(hit.u, hit.v, &hit.point);
+            if let Some(pdf) = scattering_pdf {
+                emitted = weigh_emitted(emitted, &ray, pdf, scene, sampler);
+            }
+            color += throughput * emitted;
+
+            match hit.material.scatter(&ray, &hit, sampler) {
+                None => break,
+                Some(ScatterRecord::Specular {
+                    attenuation,
+                    ray: scattered_ray,
+                }) => {
+                    throughput *= attenuation;
+                    ray = scattered_ray;
+                    scattering_pdf = None;
+                }
+                Some(ScatterRecord::Diffuse { pdf }) => {
+                    color +=
+                        throughput * sample_light(&ray, &hit, pdf.as_ref(), scene, sampler, rays);
+
+                    let direction = pdf.generate(sampler);
+                    let pdf = pdf.value(&direction, sampler);
+                    let bsdf = hit.material.eval(&ray, &hit, &direction);
+                    if pdf == 0.0 || bsdf.near_zero() {
+                        break;
+                    }
+                    throughput = throughput * bsdf / pdf;
+                    ray = Ray::new(hit.point, direction, ray.time());
+                    scattering_pdf = Some(pdf);
+                }
+            }
+
+            // Past the first bounces, end the path with a chance that grows as its throughput
+            // falls, and make up for the paths ended in the ones that go on.
+            depth += 1;
+            if depth >= self.roulette_depth {
+                let survival = throughput
+                    .x()
+                    .max(throughput.y())
+                    .max(throughput.z())
+                    .min(0.95);
+                if sampler.get_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+        }
+        color
+    }
+}
+
+// The light reaching the first diffuse surface the ray meets straight from the lights and the
+// background, through up to `max_depth` bounces off mirrors and glass: the path tracer without
+// the light bouncing between diffuse surfaces.
+pub struct DirectIntegrator {
+    max_depth: usize,
+}
+
+impl DirectIntegrator {
+    pub fn new(max_depth: usize) -> Self {
+        Self { max_depth }
+    }
+}
+
+impl Integrator for DirectIntegrator {
+    fn radiance(
+        &self,
+        mut ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+
+        for _ in 0..self.max_depth {
+            *rays += 1;
+            let hit = match scene.hit(&ray, sampler) {
+                Some(hit) => hit,
+                None => return color + throughput * scene.background.value(&ray),
+            };
+            color += throughput * hit.material.emitted(hit.u, hit.v, &hit.point);
+
+            let pdf = match hit.material.scatter(&ray, &hit, sampler) {
+                None => break,
+                Some(ScatterRecord::Specular {
+                    attenuation,
+                    ray: scattered_ray,
+                }) => {
+                    throughput *= attenuation;
+                    ray = scattered_ray;
+                    continue;
+                }
+                Some(ScatterRecord::Diffuse { pdf }) => pdf,
+            };
+
+            // both strategies of the path tracer, but the scattered ray only counts the light it
+            // finds where it lands
+            color += throughput * sample_light(&ray, &hit, pdf.as_ref(), scene, sampler, rays);
+
+            let direction = pdf.generate(sampler);
+            let pdf = pdf.value(&direction, sampler);
+            let bsdf = hit.material.eval(&ray, &hit, &direction);
+            if pdf == 0.0 || bsdf.near_zero() {
+                break;
+            }
+            *rays += 1;
+            let scattered = Ray::new(hit.point, direction, ray.time());
+            let light = match scene.hit(&scattered, sampler) {
+                Some(light_hit) => {
+                    let emitted =
+                        light_hit
+                            .material
+                            .emitted(light_hit.u, light_hit.v, &light_hit.point);
+                    weigh_emitted(emitted, &scattered, pdf, scene, sampler)
+                }
+                None => scene.background.value(&scattered),
+            };
+            color += throughput * bsdf * light / pdf;
+            break;
+        }
+        color
+    }
+}
+
+// The fraction of the directions around the first surface the ray hits that reach `distance`
+// away from it without meeting anything else, in white.
+pub struct AmbientOcclusionIntegrator {
+    distance: f64,
+}
+
+impl AmbientOcclusionIntegrator {
+    pub fn new(distance: f64) -> Self {
+        Self { distance }
+    }
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        *rays += 1;
+        let hit = match scene.hit(&ray, sampler) {
+            Some(hit) => hit,
+            None => return Color::new(1.0, 1.0, 1.0),
+        };
+        // cosine-weighted, so that the directions count as much as the light they would bring
+        let direction = CosinePdf::new(&hit.normal()).generate(sampler);
+        let occluder = Ray::new(hit.point, direction.normalize(), ray.time());
+        *rays += 1;
+        let range = RangeInclusive::new(0.001, self.distance);
+        match scene.world.hit(&occluder, &range, sampler) {
+            Some(_) => Color::new(0.0, 0.0, 0.0),
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// The outward normal of the first surface the ray hits, from [-1, 1] to [0, 1] per axis.
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        *rays += 1;
+        match scene.hit(&ray, sampler) {
+            Some(hit) => {
+                let normal = match hit.normal {
+                    Normal::Front(n) => n,
+                    Normal::Back(n) => -n,
+                };
+                (normal + Color::new(1.0, 1.0, 1.0)) / 2.0
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// The surface coordinates of the first surface the ray hits, u in red and v in green.
+pub struct UvIntegrator;
+
+impl Integrator for UvIntegrator {
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        *rays += 1;
+        match scene.hit(&ray, sampler) {
+            Some(hit) => Color::new(hit.u, hit.v, 0.0),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// The distance to the first surface the ray hits, black at the ray's origin and white at `range`
+// and beyond, where the rays that miss are too.
+pub struct DepthIntegrator {
+    range: f64,
+}
+
+impl DepthIntegrator {
+    pub fn new(range: f64) -> Self {
+        Self { range }
+    }
+}
+
+impl Integrator for DepthIntegrator {
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        *rays += 1;
+        let depth = match scene.hit(&ray, sampler) {
+            Some(hit) => (hit.t * ray.direction().length() / self.range).min(1.0),
+            None => 1.0,
+        };
+        Color::new(depth, depth, depth)
+    }
+}
+
+// The BVH nodes visited to find what the ray hits, from blue for none through green to red for
+// `BVH_COST_SCALE` and more.
+pub struct BvhCostIntegrator;
+
+impl Integrator for BvhCostIntegrator {
+    fn radiance(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        *rays += 1;
+        let (_, visited) = bvh::count_nodes_visited(|| scene.hit(&ray, sampler));
+        let cost = (visited as f64 / BVH_COST_SCALE).min(1.0);
+        let (a, b) = ((2.0 * cost - 1.0).max(0.0), (1.0 - 2.0 * cost).max(0.0));
+        Color::new(a, 1.0 - a - b, b)
+    }
+}
+
+// The light reaching the hit from a direction picked towards the lights, weighed against the
+// chance of the material scattering towards it.
+fn sample_light(
+    ray: &Ray,
+    hit: &HitRecord,
+    scattering: &dyn Pdf,
+    scene: &SceneView,
+    sampler: &mut dyn Sampler,
+    rays: &mut u64,
+) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    if scene.lights.is_empty() {
+        return black;
+    }
+    let lights = HittablePdf::new(scene.lights, hit.point);
+    let direction = lights.generate(sampler);
+    let light_pdf = lights.value(&direction, sampler);
+    if light_pdf == 0.0 {
+        return black;
+    }
+    let bsdf = hit.material.eval(ray, hit, &direction);
+    if bsdf.near_zero() {
+        return black;
+    }
+
+    // whatever the ray meets first, a light in the way counts too, something else blocks it
+    *rays += 1;
+    let to_light = Ray::new(hit.point, direction, ray.time());
+    match scene.hit(&to_light, sampler) {
+        Some(light_hit) => {
+            let emitted = light_hit
+                .material
+                .emitted(light_hit.u, light_hit.v, &light_hit.point);
+            let weight = power_heuristic(light_pdf, scattering.value(&direction, sampler));
+            bsdf * emitted * (weight / light_pdf)
+        }
+        None => black,
+    }
+}
+
+// The light emitted where a ray scattered from a diffuse surface with density `pdf` lands, weighed
+// against sampling the lights from there, which could have found it too.
+fn weigh_emitted(
+    emitted: Color,
+    ray: &Ray,
+    pdf: f64,
+    scene: &SceneView,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    if emitted.near_zero() {
+        return emitted;
+    }
+    let light_pdf = HittablePdf::new(scene.lights, ray.origin()).value(&ray.direction(), sampler);
+    emitted * power_heuristic(pdf, light_pdf)
+}
+
+// The weight of a sample picked with density `pdf` by one strategy, against another strategy
+// that would have picked it with density `other_pdf`.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::SolidBackground;
+    use crate::material::{DiffuseLight, Lambertian, Material};
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::util::{dot, random_unit_vector};
+    use crate::vec3::{Point3, Vec3};
+    use std::sync::Arc;
+
+    fn sphere(radius: f64, material: Arc<dyn Material>) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), radius, material))
+    }
+
+    fn diffuse(albedo: f64) -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Box::new(SolidColor::new(
+            albedo, albedo, albedo,
+        ))))
+    }
+
+    // The mean of `n` samples of the ray from (0, 0, 5) to the center.
+    fn mean(integrator: &dyn Integrator, scene: &SceneView, n: usize) -> Color {
+        let mut sampler = IndependentSampler::new(7);
+        let mut rays = 0;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            sum += integrator.radiance(ray, scene, &mut sampler, &mut rays);
+        }
+        assert!(rays >= n as u64);
+        sum / n as f64
+    }
+
+    // A white diffuse sphere under a white sky gives back the sky exactly, every path of it.
+    #[test]
+    fn white_furnace() {
+        let mut world = HittableList::default();
+        world.add(sphere(1.0, diffuse(1.0)));
+        let scene = SceneView {
+            world: &world,
+            lights: &HittableList::default(),
+            background: &SolidBackground::new(1.0, 1.0, 1.0),
+        };
+        let color = mean(&PathIntegrator::new(None, 100), &scene, 100);
+        for c in 0..3 {
+            assert!((color[c] - 1.0).abs() < 1e-9, "{:?}", color);
+        }
+        // and on average with Russian roulette
+        let color = mean(&PathIntegrator::new(None, 0), &scene, 20_000);
+        for c in 0..3 {
+            assert!((color[c] - 1.0).abs() < 0.02, "{:?}", color);
+        }
+    }
+
+    // Inside a sphere of constant light, a diffuse sphere reflects its albedo times the light,
+    // with the light found both by sampling it and by the scattered rays.
+    #[test]
+    fn constant_emitter() {
+        let light = Arc::new(DiffuseLight::new(Box::new(SolidColor::new(0.8, 0.6, 0.4))));
+        let emitter = sphere(10.0, light);
+        let mut world = HittableList::default();
+        world.add(sphere(1.0, diffuse(0.5)));
+        world.add(emitter.clone());
+        let mut lights = HittableList::default();
+        lights.add(emitter);
+        let scene = SceneView {
+            world: &world,
+            lights: &lights,
+            background: &SolidBackground::new(0.0, 0.0, 0.0),
+        };
+        let color = mean(&PathIntegrator::new(Some(4), 3), &scene, 20_000);
+        for (c, expected) in [0.4, 0.3, 0.2].iter().enumerate() {
+            assert!((color[c] - expected).abs() < 0.02 * expected, "{:?}", color);
+        }
+
+        // without the lights to sample, the scattered rays alone find the same
+        let scene = SceneView {
+            lights: &HittableList::default(),
+            ..scene
+        };
+        let color = mean(&PathIntegrator::new(Some(4), 3), &scene, 20_000);
+        for (c, expected) in [0.4, 0.3, 0.2].iter().enumerate() {
+            assert!((color[c] - expected).abs() < 0.02 * expected, "{:?}", color);
+        }
+    }
+
+    // The normals view shows the outward unit normal, from outside the surface and inside it.
+    #[test]
+    fn unit_normals() {
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(1.0, -2.0, 0.5),
+            2.0,
+            diffuse(0.5),
+        )));
+        let scene = SceneView {
+            world: &world,
+            lights: &HittableList::default(),
+            background: &SolidBackground::new(0.0, 0.0, 0.0),
+        };
+        let center = Point3::new(1.0, -2.0, 0.5);
+        let mut sampler = IndependentSampler::new(8);
+        let mut rays = 0;
+        for distance in [10.0, 0.5] {
+            for _ in 0..100 {
+                let origin = center + random_unit_vector(&mut sampler) * distance;
+                // towards a point of the sphere, seen from outside or through it from inside
+                let target = center + random_unit_vector(&mut sampler) * 1.9;
+                let ray = Ray::new(origin, target - origin, 0.0);
+                let hit = scene.hit(&ray, &mut sampler).unwrap();
+                let color = NormalsIntegrator.radiance(ray, &scene, &mut sampler, &mut rays);
+                for c in 0..3 {
+                    assert!((0.0..=1.0).contains(&color[c]), "{:?}", color);
+                }
+                let normal = color * 2.0 - Color::new(1.0, 1.0, 1.0);
+                assert!((normal.length() - 1.0).abs() < 1e-9, "{:?}", normal);
+                let outward = (hit.point - center) / 2.0;
+                assert!(dot(&normal, &outward) > 1.0 - 1e-9);
+            }
+        }
+        // and black where nothing is hit
+        let ray = Ray::new(Point3::new(10.0, 10.0, 10.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let color = NormalsIntegrator.radiance(ray, &scene, &mut sampler, &mut rays);
+        assert_eq!((color.x(), color.y(), color.z()), (0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,349 @@
+// A small JSON parser that remembers where every value starts, so that errors found while
+// interpreting a document can point back into the source.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+pub enum Kind {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // keys in the order they appear in the document
+    Object(Vec<(String, Value)>),
+}
+
+#[derive(Debug)]
+pub struct Value {
+    pub kind: Kind,
+    pub position: Position,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            Kind::Null => "null",
+            Kind::Bool(_) => "a boolean",
+            Kind::Number(_) => "a number",
+            Kind::String(_) => "a string",
+            Kind::Array(_) => "an array",
+            Kind::Object(_) => "an object",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse(source: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            position: self.position(),
+            message: message.to_owned(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        if self.peek() == Some(expected) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(
+            self.peek(),
+            Some(' ') | Some('\t') | Some('\n') | Some('\r')
+        ) {
+            self.bump();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+        let position = self.position();
+        let kind = match self.peek() {
+            Some('{') => self.object()?,
+            Some('[') => self.array()?,
+            Some('"') => Kind::String(self.string()?),
+            Some('t') => self.keyword("true", Kind::Bool(true))?,
+            Some('f') => self.keyword("false", Kind::Bool(false))?,
+            Some('n') => self.keyword("null", Kind::Null)?,
+            Some(c) if c == '-' || c.is_ascii_digit() => Kind::Number(self.number()?),
+            Some(c) => return Err(self.error(&format!("unexpected character `{}`", c))),
+            None => return Err(self.error("unexpected end of input")),
+        };
+        Ok(Value { kind, position })
+    }
+
+    fn keyword(&mut self, word: &str, kind: Kind) -> Result<Kind, ParseError> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("invalid literal, expected `{}`", word)));
+            }
+            self.bump();
+        }
+        Ok(kind)
+    }
+
+    fn number(&mut self) -> Result<f64, ParseError> {
+        let position = self.position();
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.bump();
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map_err(|_| ParseError {
+            position,
+            message: format!("invalid number `{}`", text),
+        })
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = self
+                .bump()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let escaped = self
+                        .bump()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    s.push(match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let mut code = 0;
+                            for _ in 0..4 {
+                                let digit = self
+                                    .bump()
+                                    .and_then(|d| d.to_digit(16))
+                                    .ok_or_else(|| self.error("invalid unicode escape"))?;
+                                code = code * 16 + digit;
+                            }
+                            char::from_u32(code)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    });
+                }
+                _ => s.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Kind, ParseError> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(Kind::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            // peek first, so that an error points at the unexpected character
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(']') => {
+                    self.bump();
+                    return Ok(Kind::Array(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Kind, ParseError> {
+        self.expect('{')?;
+        let mut entries: Vec<(String, Value)> = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(Kind::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let position = self.position();
+            let key = self.string()?;
+            if entries.iter().any(|(k, _)| *k == key) {
+                return Err(ParseError {
+                    position,
+                    message: format!("duplicate key `{}`", key),
+                });
+            }
+            self.skip_whitespace();
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some('}') => {
+                    self.bump();
+                    return Ok(Kind::Object(entries));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_at(source: &str) -> (usize, usize, String) {
+        let e = parse(source).unwrap_err();
+        (e.position.line, e.position.column, e.message)
+    }
+
+    #[test]
+    fn values_and_positions() {
+        let value =
+            parse("{\n  \"a\": [1, -2.5e1, true, null],\n  \"b\": \"x\\u0041\\n\"\n}").unwrap();
+        let entries = match &value.kind {
+            Kind::Object(entries) => entries,
+            _ => panic!("expected an object"),
+        };
+        assert_eq!(value.position, Position { line: 1, column: 1 });
+        assert_eq!(entries[0].0, "a");
+        assert_eq!(entries[0].1.position, Position { line: 2, column: 8 });
+        match &entries[0].1.kind {
+            Kind::Array(items) => {
+                assert!(matches!(items[1].kind, Kind::Number(n) if n == -25.0));
+                assert!(matches!(items[2].kind, Kind::Bool(true)));
+                assert!(matches!(items[3].kind, Kind::Null));
+                assert_eq!(
+                    items[3].position,
+                    Position {
+                        line: 2,
+                        column: 26
+                    }
+                );
+            }
+            _ => panic!("expected an array"),
+        }
+        assert!(matches!(&entries[1].1.kind, Kind::String(s) if s == "xA\n"));
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error_at("[1, 2"), (1, 6, "expected `,` or `]`".to_owned()));
+        assert_eq!(error_at("[1 2]"), (1, 4, "expected `,` or `]`".to_owned()));
+        assert_eq!(
+            error_at("{\n  \"a\": 1\n  \"b\": 2\n}"),
+            (3, 3, "expected `,` or `}`".to_owned())
+        );
+        assert_eq!(
+            error_at("{\"a\": 1,\n \"a\": 2}"),
+            (2, 2, "duplicate key `a`".to_owned())
+        );
+        assert_eq!(
+            error_at("{\"a\": 1.2.3}"),
+            (1, 7, "invalid number `1.2.3`".to_owned())
+        );
+        assert_eq!(
+            error_at("[tru]"),
+            (1, 5, "invalid literal, expected `true`".to_owned())
+        );
+        assert_eq!(
+            error_at("\n\n  @"),
+            (3, 3, "unexpected character `@`".to_owned())
+        );
+        assert_eq!(error_at("\"abc"), (1, 5, "unterminated string".to_owned()));
+        assert_eq!(
+            error_at("\"\\q\""),
+            (1, 4, "invalid escape sequence".to_owned())
+        );
+        assert_eq!(
+            error_at("{} x"),
+            (1, 4, "unexpected trailing characters".to_owned())
+        );
+        assert_eq!(error_at(""), (1, 1, "unexpected end of input".to_owned()));
+    }
+}
@@ -0,0 +1,32 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod film;
+pub mod filter;
+pub mod hit;
+pub mod image;
+pub mod integrator;
+pub mod json;
+pub mod material;
+pub mod medium;
+pub mod noise;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod pdf;
+pub mod ray;
+pub mod rect;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
+pub mod triangle;
+pub mod util;
+pub mod vec3;
+pub mod zlib;
@@ -1,202 +1,260 @@
-mod aabb;
-mod bvh;
-mod camera;
-mod hit;
-mod material;
-mod ray;
-mod sphere;
-mod texture;
-mod util;
-mod vec3;
-
-use std::ops::RangeInclusive;
-use std::sync::{Arc, Mutex};
-
-use crate::camera::{Camera, APSECT_RATIO};
-use crate::hit::{Hittable, HittableList};
-use crate::material::{Dielectric, Lambertian, Metal};
-use crate::ray::Ray;
-use crate::sphere::{MovingSphere, Sphere};
-use crate::texture::{Checker, SolidColor};
-use crate::util::{random_f64, random_f64_range};
-use crate::vec3::{Color, Point3, Vec3};
-
-use crossbeam::channel::unbounded;
-use rand::Rng;
-
-const SAMPLES_PER_PIXEL: usize = 500;
-const MAX_DEPTH: usize = 50;
-
-const MAX_THREADS: usize = 12;
-
-fn random_scene() -> HittableList {
-    let mut world = HittableList::default();
-
-    let checker = Checker::new(
-        Box::new(SolidColor::new(0.2, 0.3, 0.1)),
-        Box::new(SolidColor::new(0.9, 0.9, 0.9)),
-    );
-    let ground_material = Arc::new(Lambertian::new(Box::new(checker)));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_f64();
-            let center = Point3::new(
-                a as f64 + 0.9 * random_f64(),
-                0.2,
-                b as f64 + 0.9 * random_f64(),
-            );
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.0 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    world.add(Arc::new(Sphere::new(
-                        center,
-                        0.2,
-                        Arc::new(Lambertian::new(Box::new(SolidColor::new(
-                            random_f64(),
-                            random_f64(),
-                            random_f64(),
-                        )))),
-                    )));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    world.add(Arc::new(MovingSphere::new(
-                        center,
-                        center + Vec3::new(0.0, random_f64_range(0.0..0.5), 0.0),
-                        0.0,
-                        1.0,
-                        0.2,
-                        Arc::new(Metal::new(
-                            Color::new(
-                                random_f64_range(0.5..1.0),
-                                random_f64_range(0.5..1.0),
-                                random_f64_range(0.5..1.0),
-                            ),
-                            random_f64_range(0.0..0.5) / 2.0,
-                        )),
-                    )));
-                } else {
-                    world.add(Arc::new(Sphere::new(
-                        center,
-                        0.2,
-                        Arc::new(Dielectric::new(1.5)),
-                    )));
-                }
-            }
-        }
-    }
+mod cli;
+mod scenes;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-    world.add(Arc::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        Arc::new(Lambertian::new(Box::new(SolidColor::new(0.4, 0.2, 0.1)))),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        Arc::new(Dielectric::new(1.5)),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
-    )));
-
-    world
+use raytracing::film::Film;
+use raytracing::filter::{FilterKind, PixelFilter};
+use raytracing::image::Image;
+use raytracing::integrator::{
+    AmbientOcclusionIntegrator, BvhCostIntegrator, DepthIntegrator, DirectIntegrator, Integrator,
+    IntegratorKind, NormalsIntegrator, PathIntegrator, UvIntegrator, DEFAULT_SPECULAR_DEPTH,
+};
+use raytracing::output;
+use raytracing::renderer::{RenderSettings, RenderStats, Renderer};
+use raytracing::sampler::SamplerKind;
+use raytracing::scene::Scene;
+use raytracing::tonemap::ColorPipeline;
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::cli::{Command, Options, OutputFormat};
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}\n\nFor more information, try `--help`.", message);
+    process::exit(2);
 }
 
 fn main() {
-    // Image
-    let image_width = 400_usize;
-    let image_height = (image_width as f64 / APSECT_RATIO).floor() as usize;
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::usage());
+            return;
+        }
+        Err(e) => fail(&e),
+    };
+
+    let resumed = options.resume.as_ref().map(|path| {
+        Film::load(path)
+            .unwrap_or_else(|e| fail(&format!("cannot resume `{}`: {}", path.display(), e)))
+    });
+    let seed = match (&resumed, options.seed) {
+        (Some(film), Some(seed)) if seed != film.seed() => fail(&format!(
+            "the checkpoint was rendered with the seed {}, not {}",
+            film.seed(),
+            seed
+        )),
+        (Some(film), _) => film.seed(),
+        (None, seed) => seed.unwrap_or(0),
+    };
+    let sampler = match (&resumed, options.sampler) {
+        (Some(film), Some(sampler)) if sampler != film.sampler() => fail(&format!(
+            "the checkpoint was rendered with the {:?} sampler, not {:?}",
+            film.sampler(),
+            sampler
+        )),
+        (Some(film), _) => film.sampler(),
+        (None, sampler) => sampler.unwrap_or(SamplerKind::Independent),
+    };
+    let filter = match (&resumed, options.filter) {
+        (Some(film), Some(filter)) if filter != film.filter() => fail(&format!(
+            "the checkpoint was rendered with a {:?} filter of radius {}, not a {:?} filter of \
+             radius {}",
+            film.filter().kind(),
+            film.filter().radius(),
+            filter.kind(),
+            filter.radius()
+        )),
+        (Some(film), _) => film.filter(),
+        (None, filter) => filter.unwrap_or(PixelFilter::new(
+            FilterKind::Box,
+            FilterKind::Box.default_radius(),
+        )),
+    };
+
+    // The stratified sampler spreads the samples over strata made for this many, a resumed
+    // render with another count would mix two stratifications. The others can take more.
+    let max_samples = match (&resumed, options.samples_per_pixel) {
+        (Some(film), Some(samples))
+            if sampler == SamplerKind::Stratified && samples != film.max_samples() =>
+        {
+            fail(&format!(
+                "the checkpoint was rendered with the stratified sampler for {} samples per \
+                 pixel, not {}",
+                film.max_samples(),
+                samples
+            ))
+        }
+        (_, Some(samples)) => samples,
+        (Some(film), None) => film.max_samples(),
+        (None, None) => cli::DEFAULT_SAMPLES_PER_PIXEL,
+    };
 
     // World
-    let world = random_scene();
+    // anything that is not the name of a built-in scene is a scene file
+    let scene = match scenes::build(&options.scene, seed) {
+        Some(scene) => scene,
+        None => Scene::load(&options.scene).unwrap_or_else(|e| fail(&e.to_string())),
+    };
+
+    // Image
+    let mut film = match resumed {
+        Some(mut film) => {
+            let size = (film.width(), film.height());
+            let asked = (options.width, options.height);
+            if asked.0.is_some_and(|w| w != size.0) || asked.1.is_some_and(|h| h != size.1) {
+                fail(&format!(
+                    "the checkpoint is {}x{}, it cannot be resumed at another size",
+                    size.0, size.1
+                ));
+            }
+            film.set_max_samples(max_samples);
+            film
+        }
+        None => {
+            let (width, height) = options.resolution(scene.camera.aspect_ratio);
+            Film::new(width, height, seed, sampler, filter, max_samples)
+        }
+    };
+    let (image_width, image_height) = (film.width(), film.height());
 
     // Camera
-    let camera = Arc::new(Camera::new(
-        Point3::new(13.0, 2.0, 3.0),
-        Point3::new(0.0, 0.0, 0.0),
-        20.0,
-        0.1,  // aperture
-        10.0, // dist_to_focus
-        0.0,
-        1.0,
-    ));
-    println!("P3\n{} {}\n255", image_width, image_height);
-
-    let (s, r) = unbounded();
-    let canvas = Arc::new(Mutex::new(vec![
-        vec![Color::default(); image_width];
-        image_height
-    ]));
-    let finished = Arc::new(Mutex::new(vec![0; image_height]));
-
-    for j in (0..image_height).rev() {
-        for i in 0..image_width {
-            s.send((i, j)).unwrap();
+    let camera = scene.camera.build(image_width as f64 / image_height as f64);
+    // the scale of the scene as the camera sees it, for the views that need one
+    let view_distance = (scene.camera.lookat - scene.camera.lookfrom).length();
+
+    // Integrator
+    let integrator: Box<dyn Integrator> = match options.integrator {
+        IntegratorKind::Path => Box::new(PathIntegrator::new(
+            options.max_depth,
+            options.roulette_depth,
+        )),
+        IntegratorKind::Direct => Box::new(DirectIntegrator::new(
+            options.max_depth.unwrap_or(DEFAULT_SPECULAR_DEPTH),
+        )),
+        IntegratorKind::AmbientOcclusion => Box::new(AmbientOcclusionIntegrator::new(
+            options.ao_distance.unwrap_or(view_distance / 10.0),
+        )),
+        IntegratorKind::Normals => Box::new(NormalsIntegrator),
+        IntegratorKind::Uv => Box::new(UvIntegrator),
+        IntegratorKind::Depth => Box::new(DepthIntegrator::new(2.0 * view_distance)),
+        IntegratorKind::BvhCost => Box::new(BvhCostIntegrator),
+    };
+
+    // Open the output before spending time on rendering.
+    let mut out: Box<dyn Write> = match &options.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => fail(&format!("cannot create `{}`: {}", path.display(), e)),
+        },
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+
+    let world = Arc::new(scene.world.into_bvh(&mut SmallRng::seed_from_u64(seed)));
+    let renderer = Renderer::new(
+        world,
+        scene.lights,
+        camera,
+        scene.background,
+        integrator,
+        RenderSettings {
+            threads: options.threads,
+            max_samples,
+            min_samples: options.min_samples,
+            error_threshold: options.error_threshold,
+            filter,
+            sampler,
+        },
+    );
+
+    // Render in passes until no pixel needs more samples, saving a checkpoint now and then.
+    let mut last_checkpoint = Instant::now();
+    let mut unsaved = false;
+    let (mut samples, mut rays, mut elapsed) = (0, 0, Duration::ZERO);
+    loop {
+        let stats = renderer.render_pass(&mut film, options.pass_samples);
+        if stats.samples == 0 {
+            break;
         }
-    }
+        samples += stats.samples;
+        rays += stats.rays;
+        elapsed += stats.elapsed;
+        eprintln!(
+            "{:.1} samples per pixel on average, {:.3} Mrays/s",
+            film.total_samples() as f64 / (image_width * image_height) as f64,
+            stats.rays_per_second() / 1E6
+        );
 
-    let world = Arc::new(world.into_bvh());
-    let mut handles = vec![];
-    for _ in 0..MAX_THREADS {
-        let world = world.clone();
-        let camera = camera.clone();
-        let r = r.clone();
-        let canvas = canvas.clone();
-        let finished = finished.clone();
-        let h = std::thread::spawn(move || {
-            let mut rng = rand::thread_rng();
-            while let Ok((i, j)) = r.recv() {
-                let mut color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..SAMPLES_PER_PIXEL {
-                    let u = (i as f64 + rng.gen_range(0.0..1.0)) / (image_width - 1) as f64;
-                    let v = (j as f64 + rng.gen_range(0.0..1.0)) / (image_height - 1) as f64;
-                    let ray = camera.get_ray(u, v);
-                    let sample_color = ray_color(ray, world.clone(), MAX_DEPTH);
-                    color += sample_color;
-                }
-                canvas.lock().unwrap()[j][i] = color / SAMPLES_PER_PIXEL as f64;
-
-                finished.lock().unwrap()[j] += 1;
-                if finished.lock().unwrap()[j] == image_width {
-                    eprintln!("\r Scanline remaining: {}", j);
-                }
-            }
-        });
-        handles.push(h);
+        unsaved = true;
+        if last_checkpoint.elapsed() >= options.checkpoint_interval {
+            save_checkpoint(&film, options.checkpoint.as_deref());
+            last_checkpoint = Instant::now();
+            unsaved = false;
+        }
     }
-
-    drop(s);
-    for h in handles {
-        h.join().unwrap();
+    if unsaved {
+        save_checkpoint(&film, options.checkpoint.as_deref());
+    }
+    if samples > 0 {
+        let stats = RenderStats {
+            samples,
+            rays,
+            elapsed,
+        };
+        eprintln!(
+            "{} rays in {:.2}s, {:.3} Mrays/s",
+            stats.rays,
+            stats.elapsed.as_secs_f64(),
+            stats.rays_per_second() / 1E6
+        );
     }
 
-    for j in (0..image_height).rev() {
-        for i in 0..image_width {
-            println!("{}", canvas.lock().unwrap()[j][i]);
+    let pipeline = ColorPipeline::new(options.exposure, options.tone_map, options.dither);
+    if let Some((path, format)) = &options.sample_map {
+        let map = film.sample_count_image(max_samples);
+        let result = File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write_image(&mut out, &map, *format, &pipeline)?;
+            out.flush()
+        });
+        if let Err(e) = result {
+            fail(&format!("cannot write `{}`: {}", path.display(), e));
         }
     }
+    let result = write_image(&mut out, &film.image(), options.format, &pipeline);
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        fail(&format!("cannot write the image: {}", e));
+    }
 }
 
-fn ray_color(ray: Ray, world: Arc<dyn Hittable>, depth: usize) -> Color {
-    if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
+fn save_checkpoint(film: &Film, path: Option<&Path>) {
+    if let Some(path) = path {
+        if let Err(e) = film.save(path) {
+            fail(&format!(
+                "cannot save the checkpoint `{}`: {}",
+                path.display(),
+                e
+            ));
+        }
     }
+}
 
-    if let Some(hit) = world.hit(&ray, &RangeInclusive::new(0.001, std::f64::INFINITY)) {
-        if let Some((attenuation, scattered_ray)) = hit.material.scatter(&ray, &hit) {
-            return attenuation * ray_color(scattered_ray, world, depth - 1);
-        }
-        return Color::new(0.0, 0.0, 0.0);
+fn write_image(
+    out: &mut dyn Write,
+    image: &Image,
+    format: OutputFormat,
+    pipeline: &ColorPipeline,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Ppm => output::write_ppm(out, image, pipeline),
+        OutputFormat::Png => output::write_png(out, image, pipeline),
+        OutputFormat::Hdr => output::write_hdr(out, image),
     }
-    let t = (ray.direction().normalize().y() + 1.0) * 0.5;
-    Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
 }
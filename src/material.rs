@@ -1,13 +1,42 @@
+use std::f64::consts::PI;
+
 use crate::hit::{HitRecord, Normal};
+use crate::pdf::{CosinePdf, Pdf, SpherePdf};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::texture::Texture;
-use crate::util::{
-    dot, random_f64, random_in_unit_sphere, random_unit_vector, reflect, reflectance, refract,
-};
-use crate::vec3::Color;
+use crate::util::{dot, random_f64, random_in_unit_sphere, reflect, reflectance, refract};
+use crate::vec3::{Color, Point3, Vec3};
+
+// How a material scatters the light arriving along a ray.
+pub enum ScatterRecord {
+    // Along a single direction picked by the material, such as a mirror's, with the fraction of
+    // the light it passes on.
+    Specular { attenuation: Color, ray: Ray },
+    // In many directions, picked from `pdf` and weighed by `Material::eval`. Lights can then be
+    // sampled directly too.
+    Diffuse { pdf: Box<dyn Pdf> },
+}
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)>;
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
+
+    // The fraction of the light arriving from `direction` that is scattered along the ray, per
+    // unit of solid angle: the BSDF times the cosine with the normal. Only needed by the materials
+    // that scatter diffusely.
+    fn eval(&self, _ray: &Ray, _hit_record: &HitRecord, _direction: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // The radiance emitted by the material itself, only lights emit anything.
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
 }
 
 pub struct Lambertian {
@@ -21,16 +50,23 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
-        let mut direction = hit_record.normal() + random_unit_vector();
-        if direction.near_zero() {
-            direction = hit_record.normal()
-        }
-        Some((
-            self.albedo
-                .value(hit_record.u, hit_record.v, &hit_record.point),
-            Ray::new(hit_record.point, direction, ray.time()),
-        ))
+    // Cosine-weighted, like the light it reflects.
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        hit_record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Diffuse {
+            pdf: Box::new(CosinePdf::new(&hit_record.normal())),
+        })
+    }
+
+    fn eval(&self, _ray: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = dot(&hit_record.normal(), &direction.normalize());
+        self.albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point)
+            * (cosine.max(0.0) / PI)
     }
 }
 
@@ -49,15 +85,23 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let direction = reflect(&ray.direction().normalize(), &hit_record.normal());
         let scattered = Ray::new(
             hit_record.point,
-            direction + random_in_unit_sphere() * self.fuzz,
+            direction + random_in_unit_sphere(sampler) * self.fuzz,
             ray.time(),
         );
         if dot(&scattered.direction(), &hit_record.normal()) > 0.0 {
-            Some((self.albedo, scattered))
+            Some(ScatterRecord::Specular {
+                attenuation: self.albedo,
+                ray: scattered,
+            })
         } else {
             None
         }
@@ -75,7 +119,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let refraction_ratio = match hit_record.normal {
             Normal::Front(_) => 1.0 / self.ir,
             Normal::Back(_) => self.ir,
@@ -85,17 +134,74 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_f64()
-        {
-            reflect(&ray.direction().normalize(), &hit_record.normal())
-        } else {
-            refract(
-                &ray.direction().normalize(),
-                &hit_record.normal(),
-                refraction_ratio,
-            )
-        };
-        let scattered = Ray::new(hit_record.point, direction, ray.time());
-        Some((Color::new(1.0, 1.0, 1.0), scattered))
+        let direction =
+            if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_f64(sampler) {
+                reflect(&ray.direction().normalize(), &hit_record.normal())
+            } else {
+                refract(
+                    &ray.direction().normalize(),
+                    &hit_record.normal(),
+                    refraction_ratio,
+                )
+            };
+        Some(ScatterRecord::Specular {
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            ray: Ray::new(hit_record.point, direction, ray.time()),
+        })
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Box<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(texture: Box<dyn Texture>) -> Self {
+        Self { emit: texture }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.emit.value(u, v, p)
+    }
+}
+
+// Scatters uniformly in all directions, the phase function of a participating medium.
+pub struct Isotropic {
+    albedo: Box<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(texture: Box<dyn Texture>) -> Self {
+        Self { albedo: texture }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hit_record: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Diffuse {
+            pdf: Box::new(SpherePdf),
+        })
+    }
+
+    fn eval(&self, _ray: &Ray, hit_record: &HitRecord, _direction: &Vec3) -> Color {
+        self.albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point)
+            / (4.0 * PI)
     }
 }
@@ -0,0 +1,78 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hit::{HitRecord, Hittable, Normal};
+use crate::material::{Isotropic, Material};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::texture::Texture;
+use crate::util::random_f64;
+use crate::vec3::Vec3;
+
+// A volume of constant density filling a convex boundary, such as smoke or fog.
+// A ray passing through it scatters with a probability proportional to the distance travelled.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, texture: Box<dyn Texture>) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function: Arc::new(Isotropic::new(texture)),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        // Find where the ray enters and leaves the boundary, even if it starts inside.
+        let enter = self.boundary.hit(
+            ray,
+            &RangeInclusive::new(-f64::INFINITY, f64::INFINITY),
+            sampler,
+        )?;
+        let leave = self.boundary.hit(
+            ray,
+            &RangeInclusive::new(enter.t + 0.0001, f64::INFINITY),
+            sampler,
+        )?;
+
+        let t_enter = enter.t.max(*t_range.start()).max(0.0);
+        let t_leave = leave.t.min(*t_range.end());
+        if t_enter >= t_leave {
+            return None;
+        }
+
+        let ray_length = ray.direction().length();
+        let distance_inside_boundary = (t_leave - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * random_f64(sampler).ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        Some(HitRecord {
+            point: ray.at(t),
+            t,
+            // arbitrary, the phase function does not look at it
+            normal: Normal::Front(Vec3::new(1.0, 0.0, 0.0)),
+            material: self.phase_function.clone(),
+            u: 0.0,
+            v: 0.0,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.boundary.bounding_box()
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::{FromStr, SplitWhitespace};
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::bvh::BVH;
+use crate::hit::Hittable;
+use crate::image::{Filter, Wrap};
+use crate::material::{Dielectric, Lambertian, Material, Metal};
+use crate::texture::{ImageTexture, SolidColor};
+use crate::triangle::Triangle;
+use crate::vec3::{Color, Point3, Vec3};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Empty {
+        path: PathBuf,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Empty { path } => write!(f, "{}: mesh has no faces", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// Reads a Wavefront OBJ file, together with the MTL libraries it references, into a BVH of
+// triangles. Faces without a `usemtl` statement get `default_material`.
+pub fn load_obj<P: AsRef<Path>>(
+    path: P,
+    default_material: Arc<dyn Material>,
+) -> Result<Arc<dyn Hittable>, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+
+    let mut positions: Vec<Point3> = vec![];
+    let mut tex_coords: Vec<(f64, f64)> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut material = default_material;
+    let mut triangles: Vec<Arc<dyn Hittable>> = vec![];
+
+    for (idx, line) in source.lines().enumerate() {
+        let mut parser = LineParser::new(path, idx + 1, line);
+        let keyword = match parser.keyword() {
+            Some(k) => k,
+            None => continue,
+        };
+        match keyword {
+            "v" => positions.push(parser.vec3()?),
+            "vt" => {
+                let u = parser.number()?;
+                let v = parser.optional_number()?.unwrap_or(0.0);
+                tex_coords.push((u, v));
+            }
+            "vn" => normals.push(parser.vec3()?),
+            "f" => {
+                let mut vertices = vec![];
+                for token in parser.rest() {
+                    vertices.push(parser.face_vertex(
+                        token,
+                        positions.len(),
+                        tex_coords.len(),
+                        normals.len(),
+                    )?);
+                }
+                if vertices.len() < 3 {
+                    return Err(parser.error("face needs at least 3 vertices"));
+                }
+
+                // Triangulate the polygon as a fan around its first vertex.
+                for i in 1..vertices.len() - 1 {
+                    let corners = [vertices[0], vertices[i], vertices[i + 1]];
+                    let points = [
+                        positions[corners[0].0],
+                        positions[corners[1].0],
+                        positions[corners[2].0],
+                    ];
+                    let uvs = match (corners[0].1, corners[1].1, corners[2].1) {
+                        (Some(a), Some(b), Some(c)) => {
+                            Some([tex_coords[a], tex_coords[b], tex_coords[c]])
+                        }
+                        _ => None,
+                    };
+                    let vertex_normals = match (corners[0].2, corners[1].2, corners[2].2) {
+                        (Some(a), Some(b), Some(c)) => Some([normals[a], normals[b], normals[c]]),
+                        _ => None,
+                    };
+                    triangles.push(Arc::new(Triangle::new(
+                        points,
+                        vertex_normals,
+                        uvs,
+                        material.clone(),
+                    )));
+                }
+            }
+            "mtllib" => {
+                for name in parser.rest() {
+                    let mtl_path = path.parent().unwrap_or_else(|| Path::new("")).join(name);
+                    materials.extend(load_mtl(&mtl_path)?);
+                }
+            }
+            "usemtl" => {
+                let name = parser.name()?;
+                material = match materials.get(name) {
+                    Some(m) => m.clone(),
+                    None => return Err(parser.error(&format!("unknown material `{}`", name))),
+                };
+            }
+            // groups, objects, smoothing groups etc. carry no information we render
+            _ => {}
+        }
+    }
+
+    if triangles.is_empty() {
+        return Err(ObjError::Empty {
+            path: path.to_owned(),
+        });
+    }
+    // The split axes only affect the speed, so every mesh is built the same way.
+    let mut rng = SmallRng::seed_from_u64(0);
+    Ok(Arc::new(BVH::new(&triangles, &mut rng)))
+}
+
+#[derive(Default)]
+struct MtlDesc {
+    diffuse: Option<Color>,
+    diffuse_map: Option<ImageTexture>,
+    specular: Option<Color>,
+    shininess: Option<f64>,
+    ior: Option<f64>,
+    dissolve: Option<f64>,
+    illum: Option<u32>,
+}
+
+impl MtlDesc {
+    // Maps the MTL illumination model onto the materials we support:
+    // transparent materials become `Dielectric`, reflective ones `Metal`, anything else
+    // `Lambertian`.
+    fn build(self) -> Arc<dyn Material> {
+        let transparent = matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9))
+            || self.dissolve.is_some_and(|d| d < 1.0);
+        let reflective = matches!(self.illum, Some(3) | Some(5) | Some(8));
+        let diffuse = self.diffuse.unwrap_or_else(|| Color::new(0.8, 0.8, 0.8));
+
+        if transparent {
+            Arc::new(Dielectric::new(self.ior.unwrap_or(1.5)))
+        } else if reflective {
+            let albedo = match self.specular {
+                Some(ks) if !ks.near_zero() => ks,
+                _ => diffuse,
+            };
+            // Ns ranges from 0 to 1000, a higher exponent means a sharper reflection
+            let fuzz = 1.0
+                - (self.shininess.unwrap_or(0.0) / 1000.0)
+                    .clamp(0.0, 1.0)
+                    .sqrt();
+            Arc::new(Metal::new(albedo, fuzz))
+        } else if let Some(texture) = self.diffuse_map {
+            Arc::new(Lambertian::new(Box::new(texture)))
+        } else {
+            Arc::new(Lambertian::new(Box::new(SolidColor::new(
+                diffuse.x(),
+                diffuse.y(),
+                diffuse.z(),
+            ))))
+        }
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let source = read_file(path)?;
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlDesc)> = None;
+    for (idx, line) in source.lines().enumerate() {
+        let mut parser = LineParser::new(path, idx + 1, line);
+        let keyword = match parser.keyword() {
+            Some(k) => k,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = parser.name()?.to_owned();
+            if let Some((name, desc)) = current.replace((name, MtlDesc::default())) {
+                materials.insert(name, desc.build());
+            }
+            continue;
+        }
+
+        let desc = match current.as_mut() {
+            Some((_, desc)) => desc,
+            None => return Err(parser.error(&format!("`{}` before `newmtl`", keyword))),
+        };
+        match keyword {
+            "Kd" => desc.diffuse = Some(parser.vec3()?),
+            "map_Kd" => {
+                // the file name comes after any options
+                let name = match parser.rest().last() {
+                    Some(name) => *name,
+                    None => return Err(parser.error("missing texture file name")),
+                };
+                let texture_path = path.parent().unwrap_or_else(|| Path::new("")).join(name);
+                let texture = ImageTexture::load(&texture_path, Filter::Bilinear, Wrap::Repeat)
+                    .map_err(|e| parser.error(&format!("{}: {}", texture_path.display(), e)))?;
+                desc.diffuse_map = Some(texture);
+            }
+            "Ks" => desc.specular = Some(parser.vec3()?),
+            "Ns" => desc.shininess = Some(parser.number()?),
+            "Ni" => desc.ior = Some(parser.number()?),
+            "d" => desc.dissolve = Some(parser.number()?),
+            "Tr" => desc.dissolve = Some(1.0 - parser.number()?),
+            "illum" => {
+                let token = parser.name()?;
+                desc.illum = Some(parser.parse(token)?);
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, desc)) = current {
+        materials.insert(name, desc.build());
+    }
+    Ok(materials)
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+struct LineParser<'a> {
+    path: &'a Path,
+    line: usize,
+    tokens: SplitWhitespace<'a>,
+}
+
+impl<'a> LineParser<'a> {
+    fn new(path: &'a Path, line: usize, content: &'a str) -> Self {
+        let content = match content.find('#') {
+            Some(pos) => &content[..pos],
+            None => content,
+        };
+        Self {
+            path,
+            line,
+            tokens: content.split_whitespace(),
+        }
+    }
+
+    fn error(&self, message: &str) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_owned(),
+            line: self.line,
+            message: message.to_owned(),
+        }
+    }
+
+    fn keyword(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn rest(&mut self) -> Vec<&'a str> {
+        self.tokens.by_ref().collect()
+    }
+
+    fn name(&mut self) -> Result<&'a str, ObjError> {
+        self.tokens
+            .next()
+            .ok_or_else(|| self.error("unexpected end of line"))
+    }
+
+    fn parse<T: FromStr>(&self, token: &str) -> Result<T, ObjError> {
+        token
+            .parse()
+            .map_err(|_| self.error(&format!("invalid number `{}`", token)))
+    }
+
+    fn number(&mut self) -> Result<f64, ObjError> {
+        let token = self.name()?;
+        self.parse(token)
+    }
+
+    fn optional_number(&mut self) -> Result<Option<f64>, ObjError> {
+        match self.tokens.next() {
+            Some(token) => self.parse(token).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    // Resolves a 1-based (or negative, relative to the end) index into a 0-based one.
+    fn index(&self, token: &str, len: usize) -> Result<usize, ObjError> {
+        let idx: i64 = self.parse(token)?;
+        let resolved = if idx < 0 { len as i64 + idx } else { idx - 1 };
+        if idx == 0 || resolved < 0 || resolved >= len as i64 {
+            return Err(self.error(&format!("index {} out of range", idx)));
+        }
+        Ok(resolved as usize)
+    }
+
+    // Parses a face vertex of the form `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+    fn face_vertex(
+        &self,
+        token: &str,
+        positions: usize,
+        tex_coords: usize,
+        normals: usize,
+    ) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+        let mut parts = token.split('/');
+        let position = self.index(parts.next().unwrap_or(""), positions)?;
+        let tex_coord = match parts.next() {
+            Some("") | None => None,
+            Some(t) => Some(self.index(t, tex_coords)?),
+        };
+        let normal = match parts.next() {
+            Some("") | None => None,
+            Some(n) => Some(self.index(n, normals)?),
+        };
+        if parts.next().is_some() {
+            return Err(self.error(&format!("invalid face vertex `{}`", token)));
+        }
+        Ok((position, tex_coord, normal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `files` into a fresh directory and loads the first one as a mesh.
+    fn load(test: &str, files: &[(&str, &str)]) -> Result<Arc<dyn Hittable>, ObjError> {
+        let dir =
+            std::env::temp_dir().join(format!("raytracing-obj-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let material = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5))));
+        let result = load_obj(dir.join(files[0].0), material);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    fn parse_error_line(result: Result<Arc<dyn Hittable>, ObjError>) -> usize {
+        match result {
+            Err(ObjError::Parse { line, .. }) => line,
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn quad_is_triangulated() {
+        let mesh = load(
+            "quad",
+            &[(
+                "quad.obj",
+                "# unit square\nv 0 0 0\nv 2 0 0\nv 2 1 0\nv 0 1 0\nf 1 2 3 4\n",
+            )],
+        )
+        .unwrap();
+        assert_eq!(mesh.area(), Some(2.0));
+        let bbox = mesh.bounding_box().unwrap();
+        assert!(bbox.minimum.x() <= 0.0 && bbox.maximum.x() >= 2.0);
+    }
+
+    #[test]
+    fn negative_indices_and_materials() {
+        let mesh = load(
+            "mtl",
+            &[
+                (
+                    "mesh.obj",
+                    "mtllib mesh.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf -3 -2 -1\n",
+                ),
+                ("mesh.mtl", "newmtl red\nKd 1 0 0\n"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(mesh.area(), Some(0.5));
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let result = load("range", &[("a.obj", "v 0 0 0\nv 1 0 0\n\nf 1 2 3\n")]);
+        assert_eq!(parse_error_line(result), 4);
+    }
+
+    #[test]
+    fn zero_index() {
+        let result = load("zero", &[("a.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n")]);
+        assert_eq!(parse_error_line(result), 4);
+    }
+
+    #[test]
+    fn invalid_number() {
+        let result = load("number", &[("a.obj", "v 0 0 0\nv 1 x 0\n")]);
+        assert_eq!(parse_error_line(result), 2);
+    }
+
+    #[test]
+    fn degenerate_face() {
+        let result = load("face", &[("a.obj", "v 0 0 0\nv 1 0 0\nf 1 2\n")]);
+        assert_eq!(parse_error_line(result), 3);
+    }
+
+    #[test]
+    fn unknown_material() {
+        let result = load("unknown", &[("a.obj", "v 0 0 0\nusemtl missing\n")]);
+        assert_eq!(parse_error_line(result), 2);
+    }
+
+    #[test]
+    fn mtl_error_reports_its_own_line() {
+        let result = load(
+            "mtlerror",
+            &[
+                ("a.obj", "mtllib a.mtl\n"),
+                ("a.mtl", "# comment\nKd 1 1 1\n"),
+            ],
+        );
+        match result {
+            Err(ObjError::Parse { path, line, .. }) => {
+                assert!(path.ends_with("a.mtl"));
+                assert_eq!(line, 2);
+            }
+            _ => panic!("expected a parse error in the MTL file"),
+        }
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let result = load("empty", &[("a.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\n")]);
+        assert!(matches!(result, Err(ObjError::Empty { .. })));
+    }
+
+    #[test]
+    fn missing_file() {
+        let material = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5))));
+        let result = load_obj("/nonexistent/mesh.obj", material);
+        assert!(matches!(result, Err(ObjError::Io { .. })));
+    }
+}
@@ -0,0 +1,33 @@
+use crate::util::cross;
+use crate::vec3::Vec3;
+
+// An orthonormal basis around a direction `w`, to turn vectors given relative to it into world
+// coordinates.
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(direction: &Vec3) -> Self {
+        let w = direction.normalize();
+        // any vector not parallel to w
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = cross(&w, &a).normalize();
+        let u = cross(&w, &v);
+        Self { u, v, w }
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        self.u * a + self.v * b + self.w * c
+    }
+}
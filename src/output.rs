@@ -0,0 +1,347 @@
+// Writers for the rendered image.
+
+use std::io::{self, Write};
+
+use crate::image::{paeth, Image, PNG_SIGNATURE};
+use crate::tonemap::ColorPipeline;
+use crate::vec3::Color;
+use crate::zlib;
+
+// The 8-bit RGB pixels, top row first.
+fn rgb8_rows(image: &Image, pipeline: &ColorPipeline) -> Vec<Vec<u8>> {
+    (0..image.height())
+        .map(|y| {
+            (0..image.width())
+                .flat_map(|x| pipeline.to_rgb8(image.pixel(x, y), x, y))
+                .collect()
+        })
+        .collect()
+}
+
+// Writes a binary PPM (P6).
+pub fn write_ppm(out: &mut dyn Write, image: &Image, pipeline: &ColorPipeline) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    for row in rgb8_rows(image, pipeline) {
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let mut checked = kind.to_vec();
+    checked.extend_from_slice(data);
+    out.write_all(&crc32(&checked).to_be_bytes())
+}
+
+// Filters a scanline with each of the five PNG filters and keeps the one with the smallest sum
+// of absolute differences, the usual heuristic for what compresses best.
+fn filter_row(row: &[u8], previous: &[u8], out: &mut Vec<u8>) {
+    const BPP: usize = 3;
+    let mut best: Option<(u64, Vec<u8>)> = None;
+    for filter in 0..5_u8 {
+        let mut filtered = Vec::with_capacity(row.len() + 1);
+        filtered.push(filter);
+        for i in 0..row.len() {
+            let a = if i >= BPP { row[i - BPP] } else { 0 };
+            let b = previous[i];
+            let c = if i >= BPP { previous[i - BPP] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                _ => paeth(a, b, c),
+            };
+            filtered.push(row[i].wrapping_sub(predicted));
+        }
+        let cost = filtered[1..]
+            .iter()
+            .map(|&x| (x as i8).unsigned_abs() as u64)
+            .sum();
+        if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+            best = Some((cost, filtered));
+        }
+    }
+    out.extend(best.unwrap().1);
+}
+
+// Writes an 8-bit RGB PNG, tagged as sRGB.
+pub fn write_png(out: &mut dyn Write, image: &Image, pipeline: &ColorPipeline) -> io::Result<()> {
+    out.write_all(&PNG_SIGNATURE)?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&(image.width() as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height() as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, adaptive filtering, not interlaced
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+    // perceptual rendering intent
+    write_chunk(out, b"sRGB", &[0])?;
+
+    let rows = rgb8_rows(image, pipeline);
+    let mut filtered = vec![];
+    let mut previous = vec![0; image.width() * 3];
+    for row in rows {
+        filter_row(&row, &previous, &mut filtered);
+        previous = row;
+    }
+    write_chunk(out, b"IDAT", &zlib::compress(&filtered))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+// Shared exponent encoding, the inverse of the decoding in `Image::load_hdr`.
+fn color_to_rgbe(color: Color) -> [u8; 4] {
+    // the largest value there is, with a mantissa of 255 and an exponent of 127
+    const MAX: f64 = 255.0 / 256.0 * 1.7014118346046923E38;
+    // negative and NaN channels cannot be represented, nor can infinite ones
+    let channel = |c: f64| if c > 0.0 { c.min(MAX) } else { 0.0 };
+    let (r, g, b) = (channel(color.x()), channel(color.y()), channel(color.z()));
+    let v = r.max(g).max(b);
+    if v < 1E-32 {
+        return [0, 0, 0, 0];
+    }
+    // v = m * 2^e with m in [0.5, 1)
+    let e = (v.log2().floor() as i32 + 1).min(127);
+    let scale = 256.0 / 2.0_f64.powi(e);
+    let mantissa = |c: f64| (c * scale).min(255.0) as u8;
+    [mantissa(r), mantissa(g), mantissa(b), (e + 128) as u8]
+}
+
+// Appends one channel of a scanline with the run length encoding of Radiance files: a count
+// above 128 repeats the next byte `count - 128` times, otherwise `count` bytes follow as is.
+fn rle_channel(values: &[u8], out: &mut Vec<u8>) {
+    const MIN_RUN: usize = 4;
+    let mut x = 0;
+    while x < values.len() {
+        // find where the next run long enough to be worth encoding starts
+        let mut run_start = x;
+        let mut run_len = 0;
+        while run_start < values.len() {
+            run_len = values[run_start..]
+                .iter()
+                .take(127)
+                .take_while(|&&v| v == values[run_start])
+                .count();
+            if run_len >= MIN_RUN {
+                break;
+            }
+            run_start += 1;
+        }
+
+        for literal in values[x..run_start].chunks(128) {
+            out.push(literal.len() as u8);
+            out.extend_from_slice(literal);
+        }
+        if run_start < values.len() {
+            out.push(128 + run_len as u8);
+            out.push(values[run_start]);
+            x = run_start + run_len;
+        } else {
+            x = run_start;
+        }
+    }
+}
+
+// Writes a Radiance RGBE image, keeping the linear values of the framebuffer.
+pub fn write_hdr(out: &mut dyn Write, image: &Image) -> io::Result<()> {
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height(),
+        image.width()
+    )?;
+
+    let width = image.width();
+    let mut line = vec![];
+    for y in 0..image.height() {
+        let pixels: Vec<[u8; 4]> = (0..width)
+            .map(|x| color_to_rgbe(image.pixel(x, y)))
+            .collect();
+        line.clear();
+        // scanlines outside these widths cannot be run length encoded
+        if (8..0x8000).contains(&width) {
+            line.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+            for channel in 0..4 {
+                let values: Vec<u8> = pixels.iter().map(|p| p[channel]).collect();
+                rle_channel(&values, &mut line);
+            }
+        } else {
+            line.extend(pixels.iter().flatten());
+        }
+        out.write_all(&line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{decode_hdr, decode_png, decode_ppm};
+    use crate::tonemap::ToneMap;
+
+    // A test pattern with smooth gradients, flat areas and colors brighter than white.
+    fn pattern(width: usize, height: usize) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let color = if y % 4 == 0 {
+                    Color::new(0.25, 0.25, 0.25)
+                } else {
+                    Color::new(
+                        x as f64 / width as f64,
+                        (x * y % 7) as f64 * 0.5,
+                        ((x + 3 * y) as f64 * 0.37).sin().abs() * 1000.0,
+                    )
+                };
+                image.set_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
+    // Checks that `decoded` holds the 8-bit pixels `pipeline` makes of `image`.
+    fn assert_rgb8(decoded: &Image, image: &Image, pipeline: &ColorPipeline) {
+        assert_eq!(
+            (decoded.width(), decoded.height()),
+            (image.width(), image.height())
+        );
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let expected = pipeline.to_rgb8(image.pixel(x, y), x, y);
+                let p = decoded.pixel(x, y);
+                assert_eq!(
+                    [p.x(), p.y(), p.z()].map(|c| (c * 255.0).round() as u8),
+                    expected,
+                    "pixel ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ppm_round_trip() {
+        let image = pattern(13, 7);
+        let pipeline = ColorPipeline::new(0.0, ToneMap::Clamp, false);
+        let mut out = vec![];
+        write_ppm(&mut out, &image, &pipeline).unwrap();
+        assert_rgb8(&decode_ppm(&out).unwrap(), &image, &pipeline);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        for (width, height) in [(1, 1), (5, 3), (64, 33)] {
+            let image = pattern(width, height);
+            let pipeline = ColorPipeline::new(-1.0, ToneMap::Aces, true);
+            let mut out = vec![];
+            write_png(&mut out, &image, &pipeline).unwrap();
+            assert_rgb8(&decode_png(&out).unwrap(), &image, &pipeline);
+        }
+    }
+
+    #[test]
+    fn png_chunk_crcs() {
+        let mut out = vec![];
+        let pipeline = ColorPipeline::new(0.0, ToneMap::Clamp, false);
+        write_png(&mut out, &pattern(4, 4), &pipeline).unwrap();
+        let mut pos = PNG_SIGNATURE.len();
+        while pos < out.len() {
+            let len = u32::from_be_bytes([out[pos], out[pos + 1], out[pos + 2], out[pos + 3]]);
+            let end = pos + 8 + len as usize;
+            let crc = u32::from_be_bytes([out[end], out[end + 1], out[end + 2], out[end + 3]]);
+            assert_eq!(crc, crc32(&out[pos + 4..end]));
+            pos = end + 4;
+        }
+        assert_eq!(pos, out.len());
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        // below 8 pixels wide the scanlines are written flat, from 8 run length encoded
+        for (width, height) in [(3, 2), (8, 1), (200, 9)] {
+            let mut image = pattern(width, height);
+            image.set_pixel(0, 0, Color::default());
+            let mut out = vec![];
+            write_hdr(&mut out, &image).unwrap();
+            let decoded = decode_hdr(&out).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (width, height));
+            for y in 0..height {
+                for x in 0..width {
+                    let (a, b) = (image.pixel(x, y), decoded.pixel(x, y));
+                    // the components share an exponent, with 8 bits of mantissa
+                    let tolerance = a.x().max(a.y()).max(a.z()) / 128.0;
+                    for i in 0..3 {
+                        assert!(
+                            (a[i] - b[i]).abs() <= tolerance,
+                            "pixel ({}, {}): {} became {}",
+                            x,
+                            y,
+                            a[i],
+                            b[i]
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hdr_out_of_range() {
+        let mut image = Image::new(2, 2);
+        image.set_pixel(0, 0, Color::new(f64::INFINITY, 0.0, 0.0));
+        image.set_pixel(1, 0, Color::new(f64::NAN, -1.0, 2.0));
+        image.set_pixel(0, 1, Color::new(1E300, 1.0, f64::NEG_INFINITY));
+        let mut out = vec![];
+        write_hdr(&mut out, &image).unwrap();
+        let decoded = decode_hdr(&out).unwrap();
+
+        // the channels share the exponent, so the others only keep half a step of the largest
+        let largest = 2.0_f64.powi(127);
+        let step = largest / 256.0;
+        let p = decoded.pixel(0, 0);
+        assert!(p.x() > 0.99 * largest && p.x() < largest);
+        assert!(p.y() < step && p.z() < step);
+        let p = decoded.pixel(1, 0);
+        assert!(p.x() < 2.0 / 128.0 && p.y() < 2.0 / 128.0);
+        assert!((p.z() - 2.0).abs() < 2.0 / 128.0);
+        let p = decoded.pixel(0, 1);
+        assert!(p.x() > 0.99 * largest && p.x() < largest);
+        assert!(p.z() < step);
+    }
+
+    #[test]
+    fn zlib_round_trip() {
+        let text = b"the quick brown fox jumps over the lazy dog, the quick brown fox".repeat(50);
+        let mut noise = vec![];
+        let mut state = 12345_u32;
+        for _ in 0..70_000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noise.push((state >> 16) as u8);
+        }
+        for data in [&b""[..], b"a", &[0; 100_000], &text, &noise] {
+            let compressed = zlib::compress(data);
+            assert_eq!(zlib::decompress(&compressed).unwrap(), data);
+            assert_eq!(zlib::inflate(&zlib::deflate(data)).unwrap(), data);
+        }
+        assert!(zlib::compress(&text).len() < text.len() / 10);
+    }
+}
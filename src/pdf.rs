@@ -0,0 +1,162 @@
+// Probability densities of directions, over solid angle, to pick the directions of scattered
+// rays from and to weigh them by.
+
+use std::f64::consts::PI;
+
+use crate::hit::Hittable;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::util::{dot, random_cosine_direction, random_unit_vector};
+use crate::vec3::{Point3, Vec3};
+
+pub trait Pdf {
+    // The density of `generate` picking the direction.
+    fn value(&self, direction: &Vec3, sampler: &mut dyn Sampler) -> f64;
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
+}
+
+// Distributed as the cosine with a direction, the normal of a diffuse surface.
+pub struct CosinePdf {
+    onb: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: &Vec3) -> Self {
+        Self {
+            onb: Onb::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3, _sampler: &mut dyn Sampler) -> f64 {
+        let cosine = dot(&direction.normalize(), &self.onb.w());
+        cosine.max(0.0) / PI
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let d = random_cosine_direction(sampler);
+        self.onb.local(d.x(), d.y(), d.z())
+    }
+}
+
+// Uniform over all directions.
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3, _sampler: &mut dyn Sampler) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        random_unit_vector(sampler)
+    }
+}
+
+// The directions towards an object, such as the lights, from a point.
+pub struct HittablePdf<'a> {
+    object: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(object: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { object, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.object.pdf_value(&self.origin, direction, sampler)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random(&self.origin, sampler)
+    }
+}
+
+// Picks from either of two densities with even odds, for instance the lights and a material,
+// to draw a single direction that can find both.
+pub struct MixturePdf<'a> {
+    pdfs: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(first: &'a dyn Pdf, second: &'a dyn Pdf) -> Self {
+        Self {
+            pdfs: [first, second],
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        0.5 * self.pdfs[0].value(direction, sampler) + 0.5 * self.pdfs[1].value(direction, sampler)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() < 0.5 {
+            self.pdfs[0].generate(sampler)
+        } else {
+            self.pdfs[1].generate(sampler)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    // Always picks the same direction, with a made-up density.
+    struct FixedPdf(Vec3, f64);
+
+    impl Pdf for FixedPdf {
+        fn value(&self, _direction: &Vec3, _sampler: &mut dyn Sampler) -> f64 {
+            self.1
+        }
+
+        fn generate(&self, _sampler: &mut dyn Sampler) -> Vec3 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn mixture_value_is_the_average() {
+        let mut sampler = IndependentSampler::new(1);
+        let cosine_pdf = CosinePdf::new(&Vec3::new(0.0, 0.0, 1.0));
+        let mixture = MixturePdf::new(&cosine_pdf, &SpherePdf);
+        for direction in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ] {
+            let cosine = dot(&direction.normalize(), &Vec3::new(0.0, 0.0, 1.0)).max(0.0);
+            let expected = 0.5 * cosine / PI + 0.5 / (4.0 * PI);
+            assert!((mixture.value(&direction, &mut sampler) - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn mixture_generates_from_both() {
+        let mut sampler = IndependentSampler::new(7);
+        sampler.start_sample(0, 0, 0);
+        let (up, down) = (
+            FixedPdf(Vec3::new(0.0, 1.0, 0.0), 1.0),
+            FixedPdf(Vec3::new(0.0, -1.0, 0.0), 3.0),
+        );
+        let mixture = MixturePdf::new(&up, &down);
+        assert_eq!(mixture.value(&Vec3::new(1.0, 0.0, 0.0), &mut sampler), 2.0);
+
+        let n = 10_000;
+        let ups = (0..n)
+            .filter(|_| mixture.generate(&mut sampler).y() > 0.0)
+            .count();
+        assert!(
+            (4_500..5_500).contains(&ups),
+            "{} of {} from the first",
+            ups,
+            n
+        );
+    }
+}
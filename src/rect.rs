@@ -0,0 +1,396 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hit::{area_pdf, FlipFace, HitRecord, Hittable, HittableList, Normal};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::util::dot;
+use crate::vec3::{Point3, Vec3};
+
+// An infinitely thin rectangle has a zero-width bounding box along its normal axis,
+// so pad it a little to keep the slab test in `AABB::hit` well defined.
+const PADDING: f64 = 0.0001;
+
+// The rectangle spans [a0, a1] x [b0, b1] on the axes `a` and `b`, and lies on the
+// plane `k` of the remaining axis.
+#[allow(clippy::too_many_arguments)]
+fn hit_rect(
+    ray: &Ray,
+    t_range: &RangeInclusive<f64>,
+    (a, b, axis): (usize, usize, usize),
+    (a0, a1): (f64, f64),
+    (b0, b1): (f64, f64),
+    k: f64,
+    outward_normal: Vec3,
+    material: &Arc<dyn Material>,
+) -> Option<HitRecord> {
+    let origin = ray.origin();
+    let direction = ray.direction();
+
+    let t = (k - origin[axis]) / direction[axis];
+    if !t_range.contains(&t) {
+        return None;
+    }
+
+    let point = ray.at(t);
+    if point[a] < a0 || point[a] > a1 || point[b] < b0 || point[b] > b1 {
+        return None;
+    }
+
+    Some(HitRecord {
+        point,
+        t,
+        normal: if dot(&direction, &outward_normal) < 0.0 {
+            Normal::Front(outward_normal)
+        } else {
+            Normal::Back(-outward_normal)
+        },
+        material: material.clone(),
+        u: (point[a] - a0) / (a1 - a0),
+        v: (point[b] - b0) / (b1 - b0),
+    })
+}
+
+// A point picked uniformly on the rectangle, with the same arguments as `hit_rect`.
+fn random_on_rect(
+    (a, b, axis): (usize, usize, usize),
+    (a0, a1): (f64, f64),
+    (b0, b1): (f64, f64),
+    k: f64,
+    sampler: &mut dyn Sampler,
+) -> Point3 {
+    let (u, v) = sampler.get_2d();
+    let mut point = Point3::new(0.0, 0.0, 0.0);
+    point[a] = a0 + u * (a1 - a0);
+    point[b] = b0 + v * (b1 - b0);
+    point[axis] = k;
+    point
+}
+
+pub struct XYRect {
+    x0: f64,
+    x1: f64,
+    y0: f64,
+    y1: f64,
+    k: f64,
+    material: Arc<dyn Material>,
+}
+
+impl XYRect {
+    pub fn new(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Arc<dyn Material>) -> Self {
+        Self {
+            x0,
+            x1,
+            y0,
+            y1,
+            k,
+            material,
+        }
+    }
+}
+
+impl Hittable for XYRect {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        hit_rect(
+            ray,
+            t_range,
+            (0, 1, 2),
+            (self.x0, self.x1),
+            (self.y0, self.y1),
+            self.k,
+            Vec3::new(0.0, 0.0, 1.0),
+            &self.material,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(
+            Point3::new(self.x0, self.y0, self.k - PADDING),
+            Point3::new(self.x1, self.y1, self.k + PADDING),
+        ))
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some((self.x1 - self.x0) * (self.y1 - self.y0))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        match self.hit(&ray, &RangeInclusive::new(0.001, f64::INFINITY), sampler) {
+            Some(hit) => area_pdf(
+                hit.t,
+                direction,
+                &hit.normal(),
+                (self.x1 - self.x0) * (self.y1 - self.y0),
+            ),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        random_on_rect(
+            (0, 1, 2),
+            (self.x0, self.x1),
+            (self.y0, self.y1),
+            self.k,
+            sampler,
+        ) - *origin
+    }
+}
+
+pub struct XZRect {
+    x0: f64,
+    x1: f64,
+    z0: f64,
+    z1: f64,
+    k: f64,
+    material: Arc<dyn Material>,
+}
+
+impl XZRect {
+    pub fn new(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Arc<dyn Material>) -> Self {
+        Self {
+            x0,
+            x1,
+            z0,
+            z1,
+            k,
+            material,
+        }
+    }
+}
+
+impl Hittable for XZRect {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        hit_rect(
+            ray,
+            t_range,
+            (0, 2, 1),
+            (self.x0, self.x1),
+            (self.z0, self.z1),
+            self.k,
+            Vec3::new(0.0, 1.0, 0.0),
+            &self.material,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(
+            Point3::new(self.x0, self.k - PADDING, self.z0),
+            Point3::new(self.x1, self.k + PADDING, self.z1),
+        ))
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some((self.x1 - self.x0) * (self.z1 - self.z0))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        match self.hit(&ray, &RangeInclusive::new(0.001, f64::INFINITY), sampler) {
+            Some(hit) => area_pdf(
+                hit.t,
+                direction,
+                &hit.normal(),
+                (self.x1 - self.x0) * (self.z1 - self.z0),
+            ),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        random_on_rect(
+            (0, 2, 1),
+            (self.x0, self.x1),
+            (self.z0, self.z1),
+            self.k,
+            sampler,
+        ) - *origin
+    }
+}
+
+pub struct YZRect {
+    y0: f64,
+    y1: f64,
+    z0: f64,
+    z1: f64,
+    k: f64,
+    material: Arc<dyn Material>,
+}
+
+impl YZRect {
+    pub fn new(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Arc<dyn Material>) -> Self {
+        Self {
+            y0,
+            y1,
+            z0,
+            z1,
+            k,
+            material,
+        }
+    }
+}
+
+impl Hittable for YZRect {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        hit_rect(
+            ray,
+            t_range,
+            (1, 2, 0),
+            (self.y0, self.y1),
+            (self.z0, self.z1),
+            self.k,
+            Vec3::new(1.0, 0.0, 0.0),
+            &self.material,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(
+            Point3::new(self.k - PADDING, self.y0, self.z0),
+            Point3::new(self.k + PADDING, self.y1, self.z1),
+        ))
+    }
+
+    fn area(&self) -> Option<f64> {
+        Some((self.y1 - self.y0) * (self.z1 - self.z0))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        match self.hit(&ray, &RangeInclusive::new(0.001, f64::INFINITY), sampler) {
+            Some(hit) => area_pdf(
+                hit.t,
+                direction,
+                &hit.normal(),
+                (self.y1 - self.y0) * (self.z1 - self.z0),
+            ),
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        random_on_rect(
+            (1, 2, 0),
+            (self.y0, self.y1),
+            (self.z0, self.z1),
+            self.k,
+            sampler,
+        ) - *origin
+    }
+}
+
+// An axis-aligned box made of six rectangles, with normals pointing out of the box.
+pub struct BoxShape {
+    minimum: Point3,
+    maximum: Point3,
+    sides: HittableList,
+}
+
+impl BoxShape {
+    pub fn new(p0: Point3, p1: Point3, material: Arc<dyn Material>) -> Self {
+        let mut sides = HittableList::default();
+
+        // The rectangles face the positive axis, so flip the ones on the minimum side.
+        sides.add(Arc::new(XYRect::new(
+            p0.x(),
+            p1.x(),
+            p0.y(),
+            p1.y(),
+            p1.z(),
+            material.clone(),
+        )));
+        sides.add(Arc::new(FlipFace::new(Arc::new(XYRect::new(
+            p0.x(),
+            p1.x(),
+            p0.y(),
+            p1.y(),
+            p0.z(),
+            material.clone(),
+        )))));
+
+        sides.add(Arc::new(XZRect::new(
+            p0.x(),
+            p1.x(),
+            p0.z(),
+            p1.z(),
+            p1.y(),
+            material.clone(),
+        )));
+        sides.add(Arc::new(FlipFace::new(Arc::new(XZRect::new(
+            p0.x(),
+            p1.x(),
+            p0.z(),
+            p1.z(),
+            p0.y(),
+            material.clone(),
+        )))));
+
+        sides.add(Arc::new(YZRect::new(
+            p0.y(),
+            p1.y(),
+            p0.z(),
+            p1.z(),
+            p1.x(),
+            material.clone(),
+        )));
+        sides.add(Arc::new(FlipFace::new(Arc::new(YZRect::new(
+            p0.y(),
+            p1.y(),
+            p0.z(),
+            p1.z(),
+            p0.x(),
+            material,
+        )))));
+
+        Self {
+            minimum: p0,
+            maximum: p1,
+            sides,
+        }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        self.sides.hit(ray, t_range, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(self.minimum, self.maximum))
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.sides.area()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.sides.pdf_value(origin, direction, sampler)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.sides.random(origin, sampler)
+    }
+}
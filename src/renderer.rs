@@ -0,0 +1,375 @@
+// Renders the image in passes of a few samples per pixel, accumulated into a `Film`. Each pass
+// is split into square tiles: the threads take tiles from a shared queue and steal from each
+// other once it runs dry, render each tile into a buffer of their own, and hand the finished
+// tiles back when they are done, so nothing is locked while rendering.
+
+use std::iter;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::film::{Film, PixelSamples};
+use crate::filter::PixelFilter;
+use crate::hit::{Hittable, HittableList};
+use crate::integrator::{Integrator, SceneView};
+use crate::sampler::SamplerKind;
+use crate::vec3::Color;
+use crossbeam::deque::{Injector, Stealer, Worker};
+
+const TILE_SIZE: usize = 32;
+
+// A rectangle of pixels, [x0, x1) x [y0, y1), with y going down from the top row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Tile {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+impl Tile {
+    fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    // The tile grown by `margin` pixels on every side, within the image.
+    fn grow(&self, margin: usize, width: usize, height: usize) -> Tile {
+        Tile {
+            x0: self.x0.saturating_sub(margin),
+            y0: self.y0.saturating_sub(margin),
+            x1: (self.x1 + margin).min(width),
+            y1: (self.y1 + margin).min(height),
+        }
+    }
+}
+
+// The filtered samples of a tile, which reach into the margin around it.
+struct Splats {
+    area: Tile,
+    // the weighted colors and the weights, row by row
+    values: Vec<(Color, f64)>,
+}
+
+impl Splats {
+    fn new(area: Tile) -> Self {
+        Self {
+            area,
+            values: vec![(Color::default(), 0.0); area.width() * area.height()],
+        }
+    }
+
+    // Spreads a sample at (px, py), in pixels from the top left corner of the image, over the
+    // pixels the filter reaches.
+    fn add(&mut self, filter: &PixelFilter, px: f64, py: f64, color: Color) {
+        let r = filter.radius();
+        let range = |p: f64, lo: usize, hi: usize| {
+            let first = (p - r - 0.5).floor().max(lo as f64) as usize;
+            let last = ((p + r - 0.5).floor() as isize).min(hi as isize - 1);
+            first..(last + 1).max(first as isize) as usize
+        };
+        for y in range(py, self.area.y0, self.area.y1) {
+            for x in range(px, self.area.x0, self.area.x1) {
+                let weight = filter.weight(px - (x as f64 + 0.5), py - (y as f64 + 0.5));
+                if weight != 0.0 {
+                    let k = (y - self.area.y0) * self.area.width() + (x - self.area.x0);
+                    self.values[k].0 += color * weight;
+                    self.values[k].1 += weight;
+                }
+            }
+        }
+    }
+}
+
+pub struct RenderSettings {
+    pub threads: usize,
+    // no pixel takes more samples than this
+    pub max_samples: usize,
+    // With a threshold, a pixel stops taking samples once the relative error around it is below
+    // it, after at least `min_samples`. Without one, every pixel takes `max_samples`.
+    pub min_samples: usize,
+    pub error_threshold: Option<f64>,
+    pub filter: PixelFilter,
+    pub sampler: SamplerKind,
+}
+
+pub struct RenderStats {
+    pub samples: u64,
+    // the rays traced into the scene, for all the bounces
+    pub rays: u64,
+    pub elapsed: Duration,
+}
+
+impl RenderStats {
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+pub struct Renderer {
+    world: Arc<dyn Hittable>,
+    // sampled directly at every diffuse bounce
+    lights: HittableList,
+    camera: Camera,
+    background: Arc<dyn Background>,
+    integrator: Box<dyn Integrator>,
+    settings: RenderSettings,
+}
+
+impl Renderer {
+    pub fn new(
+        world: Arc<dyn Hittable>,
+        lights: HittableList,
+        camera: Camera,
+        background: Arc<dyn Background>,
+        integrator: Box<dyn Integrator>,
+        settings: RenderSettings,
+    ) -> Self {
+        Self {
+            world,
+            lights,
+            camera,
+            background,
+            integrator,
+            settings,
+        }
+    }
+
+    fn tiles(width: usize, height: usize) -> Vec<Tile> {
+        (0..height)
+            .step_by(TILE_SIZE)
+            .flat_map(|y0| {
+                (0..width).step_by(TILE_SIZE).map(move |x0| Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + TILE_SIZE).min(width),
+                    y1: (y0 + TILE_SIZE).min(height),
+                })
+            })
+            .collect()
+    }
+
+    // The number of samples the pixel takes in a pass of `samples`.
+    fn pass_samples(&self, film: &Film, x: usize, y: usize, samples: usize) -> usize {
+        let settings = &self.settings;
+        let count = film.count(x, y);
+        let converged = settings.error_threshold.is_some_and(|threshold| {
+            count >= settings.min_samples && film.local_error(x, y) <= threshold
+        });
+        if converged {
+            0
+        } else {
+            samples.min(settings.max_samples.saturating_sub(count))
+        }
+    }
+
+    // Takes up to `samples` more samples for the pixels of the film that need them. Once no pixel
+    // does the pass takes none.
+    pub fn render_pass(&self, film: &mut Film, samples: usize) -> RenderStats {
+        let start = Instant::now();
+        let tiles = Renderer::tiles(film.width(), film.height());
+        let queue = Injector::new();
+        for tile in &tiles {
+            queue.push(*tile);
+        }
+        let workers: Vec<Worker<Tile>> = (0..self.settings.threads.max(1))
+            .map(|_| Worker::new_fifo())
+            .collect();
+        let stealers: Vec<Stealer<Tile>> = workers.iter().map(|w| w.stealer()).collect();
+        let remaining = AtomicUsize::new(tiles.len());
+        let rays = AtomicU64::new(0);
+
+        let shared_film = &*film;
+        let mut finished: Vec<(Tile, Vec<PixelSamples>, Splats)> = thread::scope(|s| {
+            let handles: Vec<_> = workers
+                .into_iter()
+                .map(|local| {
+                    let (queue, stealers) = (&queue, &stealers);
+                    let (remaining, rays) = (&remaining, &rays);
+                    s.spawn(move || {
+                        let mut done = vec![];
+                        while let Some(tile) = next_tile(&local, queue, stealers) {
+                            let mut tile_rays = 0;
+                            let (pixels, splats) =
+                                self.render_tile(shared_film, tile, samples, &mut tile_rays);
+                            done.push((tile, pixels, splats));
+                            rays.fetch_add(tile_rays, Ordering::Relaxed);
+                            let left = remaining.fetch_sub(1, Ordering::Relaxed) - 1;
+                            eprint!("\r Tiles remaining: {:<8}", left);
+                        }
+                        done
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        eprintln!();
+
+        // The margins of neighboring tiles overlap, merge them in the same order every time so
+        // that the sums do not depend on which thread rendered what.
+        finished.sort_by_key(|(tile, _, _)| *tile);
+        let mut total = 0;
+        for (tile, pixels, splats) in finished {
+            for (k, pixel) in pixels.iter().enumerate() {
+                film.add_samples(
+                    tile.x0 + k % tile.width(),
+                    tile.y0 + k / tile.width(),
+                    pixel,
+                );
+                total += pixel.count as u64;
+            }
+            let area = splats.area;
+            for (k, (weighted, weight)) in splats.values.into_iter().enumerate() {
+                if weight != 0.0 {
+                    film.add_splat(
+                        area.x0 + k % area.width(),
+                        area.y0 + k / area.width(),
+                        weighted,
+                        weight,
+                    );
+                }
+            }
+        }
+        RenderStats {
+            samples: total,
+            rays: rays.into_inner(),
+            elapsed: start.elapsed(),
+        }
+    }
+
+    // The new samples of the tile's pixels, row by row, and where the filter spreads them.
+    fn render_tile(
+        &self,
+        film: &Film,
+        tile: Tile,
+        samples: usize,
+        rays: &mut u64,
+    ) -> (Vec<PixelSamples>, Splats) {
+        let (width, height, seed) = (film.width(), film.height(), film.seed());
+        let RenderSettings {
+            max_samples,
+            filter,
+            sampler,
+            ..
+        } = self.settings;
+        let mut sampler = sampler.build(seed, max_samples);
+        let margin = (filter.radius() - 0.5).ceil().max(0.0) as usize;
+        let mut splats = Splats::new(tile.grow(margin, width, height));
+        let scene = SceneView {
+            world: self.world.as_ref(),
+            lights: &self.lights,
+            background: self.background.as_ref(),
+        };
+
+        let mut pixels = Vec::with_capacity(tile.width() * tile.height());
+        for y in tile.y0..tile.y1 {
+            // the camera counts rows from the bottom
+            let j = height - 1 - y;
+            for i in tile.x0..tile.x1 {
+                let mut pixel = PixelSamples::default();
+                // carry on with the pixel's samples, so that a render in several passes, or
+                // resumed from a checkpoint with the same sampler and samples per pixel, is the
+                // same as one made in one go
+                let first = film.count(i, y);
+                for sample in first..first + self.pass_samples(film, i, y, samples) {
+                    sampler.start_sample(i, j, sample);
+                    let (du, dv) = sampler.get_2d();
+                    let u = (i as f64 + du) / (width - 1) as f64;
+                    let v = (j as f64 + dv) / (height - 1) as f64;
+                    let ray = self.camera.get_ray(u, v, sampler.as_mut());
+                    let color = self
+                        .integrator
+                        .radiance(ray, &scene, sampler.as_mut(), rays);
+                    pixel.add(color);
+                    // v goes up, the rows go down
+                    splats.add(&filter, i as f64 + du, y as f64 + 1.0 - dv, color);
+                }
+                pixels.push(pixel);
+            }
+        }
+        (pixels, splats)
+    }
+}
+
+// Takes a tile from the thread's own queue, or else from the shared one, or else steals one.
+fn next_tile(
+    local: &Worker<Tile>,
+    queue: &Injector<Tile>,
+    stealers: &[Stealer<Tile>],
+) -> Option<Tile> {
+    local.pop().or_else(|| {
+        iter::repeat_with(|| {
+            queue
+                .steal_batch_and_pop(local)
+                .or_else(|| stealers.iter().map(|s| s.steal()).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(|s| s.success())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    // A sample reaches exactly the pixels whose centers are within the radius on both axes, and
+    // none beyond the margin around its tile.
+    #[test]
+    fn splat_footprint() {
+        let (width, height) = (20, 20);
+        let tile = Tile {
+            x0: 8,
+            y0: 8,
+            x1: 12,
+            y1: 12,
+        };
+        for kind in [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+        ] {
+            for radius in [kind.default_radius(), 0.5, 1.2, 2.5] {
+                let filter = PixelFilter::new(kind, radius);
+                let margin = (filter.radius() - 0.5).ceil().max(0.0) as usize;
+                let area = tile.grow(margin, width, height);
+                for (px, py) in [(8.0, 8.0), (9.3, 10.7), (11.99, 8.5), (10.5, 10.5)] {
+                    let mut splats = Splats::new(area);
+                    splats.add(&filter, px, py, Color::new(1.0, 1.0, 1.0));
+                    for y in 0..height {
+                        for x in 0..width {
+                            let (dx, dy) = (px - (x as f64 + 0.5), py - (y as f64 + 0.5));
+                            let reached =
+                                if area.x0 <= x && x < area.x1 && area.y0 <= y && y < area.y1 {
+                                    splats.values[(y - area.y0) * area.width() + (x - area.x0)].1
+                                } else {
+                                    0.0
+                                };
+                            assert_eq!(
+                                reached,
+                                filter.weight(dx, dy),
+                                "{:?} {} from ({}, {}) at ({}, {})",
+                                kind,
+                                radius,
+                                px,
+                                py,
+                                x,
+                                y
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,479 @@
+// The random numbers of the samples, handed out one dimension, or pair of dimensions, at a time:
+// the pixel position first, then the lens and the shutter time, then those of every bounce.
+// Low-discrepancy samplers spread the samples of a pixel more evenly than independent random
+// numbers in each dimension, so the image converges faster.
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+use crate::util::{hash, sample_rng};
+
+// The largest f64 below 1, where the samples are clamped.
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// The bases of the dimensions of the Halton sequence.
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+pub trait Sampler {
+    // Starts the sample of the pixel, its dimensions start over from the first.
+    fn start_sample(&mut self, x: usize, y: usize, index: usize);
+    // The next dimension, in [0, 1).
+    fn get_1d(&mut self) -> f64;
+    // The next two dimensions, in [0, 1)^2.
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    // jittered in strata of `samples`, the samples per pixel
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    // A sampler for pixels taking up to `samples` samples.
+    pub fn build(self, seed: u64, samples: usize) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+// Where a sample is at, and the random numbers for what the others need at random.
+struct SampleState {
+    seed: u64,
+    x: usize,
+    y: usize,
+    index: usize,
+    dimension: usize,
+    rng: SmallRng,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+
+    fn start(&mut self, x: usize, y: usize, index: usize) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = sample_rng(self.seed, x, y, index);
+    }
+
+    // Takes the next `count` dimensions, returning the first, and a hash of the pixel and that
+    // dimension, the same for all the samples of the pixel.
+    fn next_dimensions(&mut self, count: usize) -> (usize, u64) {
+        let dimension = self.dimension;
+        self.dimension += count;
+        let key = hash(self.seed, &[self.x as u64, self.y as u64, dimension as u64]);
+        (dimension, key)
+    }
+}
+
+// Independent uniform random numbers.
+pub struct IndependentSampler {
+    rng: SmallRng,
+    seed: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: sample_rng(seed, 0, 0, 0),
+            seed,
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.rng = sample_rng(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen_range(0.0..1.0)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.gen_range(0.0..1.0), self.rng.gen_range(0.0..1.0))
+    }
+}
+
+// Divides each dimension in as many strata as the pixel takes samples, and each pair of
+// dimensions in a grid of about as many cells, with one jittered sample per stratum. The strata
+// are shuffled differently for every dimension, so that the dimensions are not correlated.
+pub struct StratifiedSampler {
+    state: SampleState,
+    samples: usize,
+    // the grid of the 2D strata, at least `samples` cells
+    grid: (usize, usize),
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples: usize) -> Self {
+        let samples = samples.max(1);
+        let columns = (samples as f64).sqrt().ceil() as usize;
+        let rows = samples.div_ceil(columns);
+        Self {
+            state: SampleState::new(seed),
+            samples,
+            grid: (columns, rows),
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (_, key) = self.state.next_dimensions(1);
+        let n = self.samples;
+        let stratum = permutation_element(self.state.index % n, n, key);
+        let jitter: f64 = self.state.rng.gen_range(0.0..1.0);
+        ((stratum as f64 + jitter) / n as f64).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, key) = self.state.next_dimensions(2);
+        let (columns, rows) = self.grid;
+        let cells = columns * rows;
+        let cell = permutation_element(self.state.index % cells, cells, key);
+        let (dx, dy): (f64, f64) = (
+            self.state.rng.gen_range(0.0..1.0),
+            self.state.rng.gen_range(0.0..1.0),
+        );
+        (
+            (((cell % columns) as f64 + dx) / columns as f64).min(ONE_MINUS_EPSILON),
+            (((cell / columns) as f64 + dy) / rows as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+// The Halton sequence, a radical inverse in the next prime base for each dimension, shifted by a
+// random offset per pixel and dimension. Past the dimensions it has primes for, the samples are
+// independent.
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+
+    fn dimension(&mut self, dimension: usize, key: u64) -> f64 {
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                let shift = (key >> 11) as f64 / (1u64 << 53) as f64;
+                let value = radical_inverse(self.state.index as u64, base as u64) + shift;
+                (value - value.floor()).min(ONE_MINUS_EPSILON)
+            }
+            None => self.state.rng.gen_range(0.0..1.0),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (dimension, key) = self.state.next_dimensions(1);
+        self.dimension(dimension, key)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (dimension, key) = self.state.next_dimensions(2);
+        (
+            self.dimension(dimension, key),
+            self.dimension(dimension + 1, hash(key, &[1])),
+        )
+    }
+}
+
+// Owen-scrambled Sobol points, the first two dimensions of the Sobol sequence for every pair of
+// dimensions, with the order of the points shuffled differently for each pair so that the pairs
+// are not correlated (Burley, "Practical Hash-based Owen Scrambling", 2020). The samples of a
+// pixel are best distributed at powers of two.
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+
+    fn point(&self, key: u64) -> (u32, u32) {
+        let seeds = [key as u32, (key >> 32) as u32, hash(key, &[1]) as u32];
+        let index = owen_scramble(self.state.index as u32, seeds[0]);
+        (
+            owen_scramble(index.reverse_bits(), seeds[1]),
+            owen_scramble(sobol_second_dimension(index), seeds[2]),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.state.start(x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (_, key) = self.state.next_dimensions(1);
+        to_unit(self.point(key).0)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (_, key) = self.state.next_dimensions(2);
+        let (x, y) = self.point(key);
+        (to_unit(x), to_unit(y))
+    }
+}
+
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 / (1u64 << 32) as f64
+}
+
+// The digits of `index` in `base`, mirrored around the decimal point.
+fn radical_inverse(mut index: u64, base: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let (mut reversed, mut scale) = (0, 1.0);
+    while index > 0 {
+        reversed = reversed * base + index % base;
+        scale *= inverse_base;
+        index /= base;
+    }
+    reversed as f64 * scale
+}
+
+// The second dimension of the Sobol sequence, the bits of the index times the Pascal matrix.
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let (mut result, mut direction) = (0, 1 << 31);
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+// A random permutation of the bits of `x` where each bit is flipped depending only on the bits
+// above it, keeping the stratification of points in base 2.
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    // Laine-Karras hash, which only carries changes from low bits to higher ones
+    x ^= x.wrapping_mul(0x3d20adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x05526c56);
+    x ^= x.wrapping_mul(0x53a22864);
+    x.reverse_bits()
+}
+
+// The element `i` of a random permutation of 0..n picked by `key`, without building it (Kensler,
+// "Correlated Multi-Jittered Sampling", 2013).
+fn permutation_element(i: usize, n: usize, key: u64) -> usize {
+    let (n, p) = (n as u32, key as u32);
+    let mut mask = n.wrapping_sub(1);
+    for shift in [1, 2, 4, 8, 16] {
+        mask |= mask >> shift;
+    }
+    let mut i = i as u32;
+    // cycle walking, hashing again until the value falls within 0..n
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    // added without wrapping around, which would map two elements onto the same one
+    ((i as u64 + p as u64) % n as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    // The first dimensions of each sample of a pixel, alternating 1D and 2D.
+    fn samples(kind: SamplerKind, seed: u64, (x, y): (usize, usize), n: usize) -> Vec<Vec<f64>> {
+        let mut sampler = kind.build(seed, n);
+        (0..n)
+            .map(|index| {
+                sampler.start_sample(x, y, index);
+                let mut values = vec![];
+                // past the dimensions Halton has primes for
+                for _ in 0..40 {
+                    values.push(sampler.get_1d());
+                    let (u, v) = sampler.get_2d();
+                    values.extend_from_slice(&[u, v]);
+                }
+                values
+            })
+            .collect()
+    }
+
+    #[test]
+    fn in_unit_interval() {
+        for kind in KINDS {
+            for pixel in [(0, 0), (17, 3), (1000, 999)] {
+                for sample in samples(kind, 5, pixel, 37) {
+                    assert!(
+                        sample.iter().all(|v| (0.0..1.0).contains(v)),
+                        "{:?} {:?}",
+                        kind,
+                        sample
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        for kind in KINDS {
+            let first = samples(kind, 5, (3, 4), 16);
+            assert_eq!(first, samples(kind, 5, (3, 4), 16), "{:?}", kind);
+            assert_ne!(first, samples(kind, 6, (3, 4), 16), "{:?}", kind);
+            assert_ne!(first, samples(kind, 5, (4, 3), 16), "{:?}", kind);
+
+            // a sample does not depend on the ones taken before it
+            let mut sampler = kind.build(5, 16);
+            sampler.start_sample(3, 4, 9);
+            assert_eq!(sampler.get_1d(), first[9][0], "{:?}", kind);
+        }
+    }
+
+    // The strata of `bins` per dimension that the samples of the dimensions fall in.
+    fn strata(samples: &[Vec<f64>], dimension: usize, bins: (usize, usize)) -> Vec<usize> {
+        let mut strata: Vec<usize> = samples
+            .iter()
+            .map(|s| {
+                let u = (s[dimension] * bins.0 as f64) as usize;
+                let v = (s[dimension + 1] * bins.1 as f64) as usize;
+                v * bins.0 + u
+            })
+            .collect();
+        strata.sort_unstable();
+        strata
+    }
+
+    #[test]
+    fn one_sample_per_stratum() {
+        for n in [1, 2, 4, 8, 16, 64] {
+            let all: Vec<usize> = (0..n).collect();
+            for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+                let samples = samples(kind, 11, (2, 9), n);
+                for d in (0..120).step_by(3) {
+                    // in each 1D dimension
+                    let mut bins: Vec<usize> =
+                        samples.iter().map(|s| (s[d] * n as f64) as usize).collect();
+                    bins.sort_unstable();
+                    assert_eq!(bins, all, "{:?}, {} samples, dimension {}", kind, n, d);
+                }
+            }
+
+            // in each pair of dimensions, a grid of n cells for the stratified sampler, and every
+            // such grid for Sobol
+            let stratified = samples(SamplerKind::Stratified, 11, (2, 9), n);
+            let columns = (n as f64).sqrt().ceil() as usize;
+            let grid = (columns, n.div_ceil(columns));
+            for d in (1..120).step_by(3) {
+                let mut cells = strata(&stratified, d, grid);
+                cells.dedup();
+                assert_eq!(cells.len(), n, "{} samples, dimensions {}", n, d);
+            }
+            let sobol = samples(SamplerKind::Sobol, 11, (2, 9), n);
+            for d in (1..120).step_by(3) {
+                let mut columns = 1;
+                while columns <= n {
+                    assert_eq!(
+                        strata(&sobol, d, (columns, n / columns)),
+                        all,
+                        "{} samples, dimensions {}, {} columns",
+                        n,
+                        d,
+                        columns
+                    );
+                    columns *= 2;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn permutations() {
+        for n in [1, 2, 3, 7, 16, 100, 1000] {
+            for key in [0, 1, 0xdead_beef, u64::MAX] {
+                let mut elements: Vec<usize> =
+                    (0..n).map(|i| permutation_element(i, n, key)).collect();
+                elements.sort_unstable();
+                assert_eq!(elements, (0..n).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn radical_inverses() {
+        assert_eq!(radical_inverse(0, 2), 0.0);
+        assert_eq!(radical_inverse(1, 2), 0.5);
+        assert_eq!(radical_inverse(3, 2), 0.75);
+        assert_eq!(radical_inverse(6, 2), 0.375);
+        assert!((radical_inverse(5, 3) - 7.0 / 9.0).abs() < 1e-15);
+        // the Sobol sequence's second dimension starts 0, 1/2, 3/4, 1/4
+        let second: Vec<f64> = (0..4).map(|i| to_unit(sobol_second_dimension(i))).collect();
+        assert_eq!(second, [0.0, 0.5, 0.75, 0.25]);
+    }
+}
@@ -0,0 +1,918 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::background::{Background, EnvironmentMap, GradientBackground, SolidBackground};
+use crate::camera::Camera;
+use crate::hit::{Hittable, HittableList};
+use crate::image::{Filter, Image, Wrap};
+use crate::json::{self, Kind, Position, Value};
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal};
+use crate::medium::ConstantMedium;
+use crate::obj;
+use crate::rect::{BoxShape, XYRect, XZRect, YZRect};
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{Checker, ImageTexture, NoiseKind, NoiseTexture, SolidColor, Texture};
+use crate::transform::{Matrix4, RotateX, RotateY, RotateZ, Transform, Translate};
+use crate::triangle::Triangle;
+use crate::vec3::{Color, Point3, Vec3};
+
+// Where the camera is and how it looks at the scene. The image size is chosen when rendering,
+// `aspect_ratio` is only the one the scene was composed for.
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub time0: f64,
+    pub time1: f64,
+    pub aspect_ratio: f64,
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            self.time0,
+            self.time1,
+        )
+    }
+}
+
+pub struct Scene {
+    pub world: HittableList,
+    // the objects of the world that emit light, sampled directly when rendering
+    pub lights: HittableList,
+    pub camera: CameraSettings,
+    pub background: Arc<dyn Background>,
+}
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: PathBuf,
+    // where in the file the error is, if it is about its content
+    pub position: Option<Position>,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{}:{}: {}", self.path.display(), position, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    // Loads a JSON scene description. Files it references, such as meshes and images, are
+    // looked up relative to it.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| SceneError {
+            path: path.to_owned(),
+            position: None,
+            message: e.to_string(),
+        })?;
+        let document = json::parse(&source).map_err(|e| SceneError {
+            path: path.to_owned(),
+            position: Some(e.position),
+            message: e.message,
+        })?;
+
+        let mut loader = Loader {
+            path,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            light_materials: HashSet::new(),
+        };
+        loader.scene(&document)
+    }
+}
+
+// A JSON object whose keys have been checked against the ones we understand.
+struct Fields<'v> {
+    position: Position,
+    entries: &'v [(String, Value)],
+}
+
+impl<'v> Fields<'v> {
+    fn get(&self, key: &str) -> Option<&'v Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+struct Loader<'a> {
+    path: &'a Path,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    // the names of the materials that emit light
+    light_materials: HashSet<String>,
+}
+
+impl<'a> Loader<'a> {
+    fn error(&self, position: Position, message: &str) -> SceneError {
+        SceneError {
+            path: self.path.to_owned(),
+            position: Some(position),
+            message: message.to_owned(),
+        }
+    }
+
+    fn expected(&self, value: &Value, what: &str) -> SceneError {
+        self.error(
+            value.position,
+            &format!("expected {}, found {}", what, value.type_name()),
+        )
+    }
+
+    fn resolve(&self, file: &str) -> PathBuf {
+        self.path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(file)
+    }
+
+    fn object_entries<'v>(&self, value: &'v Value) -> Result<&'v [(String, Value)], SceneError> {
+        match &value.kind {
+            Kind::Object(entries) => Ok(entries),
+            _ => Err(self.expected(value, "an object")),
+        }
+    }
+
+    fn fields<'v>(&self, value: &'v Value, allowed: &[&str]) -> Result<Fields<'v>, SceneError> {
+        let entries = self.object_entries(value)?;
+        for (key, v) in entries {
+            if !allowed.contains(&key.as_str()) {
+                return Err(self.error(
+                    v.position,
+                    &format!(
+                        "unknown field `{}`, expected one of: {}",
+                        key,
+                        allowed.join(", ")
+                    ),
+                ));
+            }
+        }
+        Ok(Fields {
+            position: value.position,
+            entries,
+        })
+    }
+
+    fn required<'v>(&self, fields: &Fields<'v>, key: &str) -> Result<&'v Value, SceneError> {
+        fields
+            .get(key)
+            .ok_or_else(|| self.error(fields.position, &format!("missing field `{}`", key)))
+    }
+
+    // The `type` of a definition, checked against the types we know.
+    fn type_of<'v>(&self, value: &'v Value, types: &[&str]) -> Result<&'v str, SceneError> {
+        let fields = Fields {
+            position: value.position,
+            entries: self.object_entries(value)?,
+        };
+        let kind = self.string(self.required(&fields, "type")?)?;
+        if !types.contains(&kind) {
+            return Err(self.error(
+                value.position,
+                &format!(
+                    "unknown type `{}`, expected one of: {}",
+                    kind,
+                    types.join(", ")
+                ),
+            ));
+        }
+        Ok(kind)
+    }
+
+    fn number(&self, value: &Value) -> Result<f64, SceneError> {
+        match value.kind {
+            Kind::Number(n) => Ok(n),
+            _ => Err(self.expected(value, "a number")),
+        }
+    }
+
+    fn string<'v>(&self, value: &'v Value) -> Result<&'v str, SceneError> {
+        match &value.kind {
+            Kind::String(s) => Ok(s),
+            _ => Err(self.expected(value, "a string")),
+        }
+    }
+
+    fn array<'v>(&self, value: &'v Value, len: Option<usize>) -> Result<&'v [Value], SceneError> {
+        match &value.kind {
+            Kind::Array(items) if len.is_none_or(|l| l == items.len()) => Ok(items),
+            _ => Err(self.expected(
+                value,
+                &match len {
+                    Some(l) => format!("an array of {} elements", l),
+                    None => "an array".to_owned(),
+                },
+            )),
+        }
+    }
+
+    fn vec3(&self, value: &Value) -> Result<Vec3, SceneError> {
+        let items = self.array(value, Some(3))?;
+        Ok(Vec3::new(
+            self.number(&items[0])?,
+            self.number(&items[1])?,
+            self.number(&items[2])?,
+        ))
+    }
+
+    fn number_or(&self, fields: &Fields, key: &str, default: f64) -> Result<f64, SceneError> {
+        fields.get(key).map_or(Ok(default), |v| self.number(v))
+    }
+
+    fn required_number(&self, fields: &Fields, key: &str) -> Result<f64, SceneError> {
+        self.number(self.required(fields, key)?)
+    }
+
+    fn required_vec3(&self, fields: &Fields, key: &str) -> Result<Vec3, SceneError> {
+        self.vec3(self.required(fields, key)?)
+    }
+
+    // The bounds of a rectangle along one axis, which must not be empty.
+    fn bounds(&self, fields: &Fields, lo: &str, hi: &str) -> Result<(f64, f64), SceneError> {
+        let (a, b) = (
+            self.required_number(fields, lo)?,
+            self.required_number(fields, hi)?,
+        );
+        if a >= b {
+            let position = self.required(fields, hi)?.position;
+            return Err(self.error(position, &format!("`{}` must be above `{}`", hi, lo)));
+        }
+        Ok((a, b))
+    }
+
+    fn scene(&mut self, document: &Value) -> Result<Scene, SceneError> {
+        let fields = self.fields(
+            document,
+            &["camera", "background", "textures", "materials", "objects"],
+        )?;
+
+        let camera = self.camera(self.required(&fields, "camera")?)?;
+        let background = match fields.get("background") {
+            Some(value) => self.background(value)?,
+            None => Arc::new(SolidBackground::new(0.0, 0.0, 0.0)),
+        };
+
+        // Definitions may only refer to the ones before them.
+        if let Some(textures) = fields.get("textures") {
+            for (name, value) in self.object_entries(textures)? {
+                let texture = self.texture_definition(value)?;
+                self.textures.insert(name.clone(), texture);
+            }
+        }
+        if let Some(materials) = fields.get("materials") {
+            for (name, value) in self.object_entries(materials)? {
+                let material = self.material_definition(value)?;
+                self.materials.insert(name.clone(), material);
+                if self.emits_light(value) {
+                    self.light_materials.insert(name.clone());
+                }
+            }
+        }
+
+        let mut world = HittableList::default();
+        let mut lights = HittableList::default();
+        let objects = self.required(&fields, "objects")?;
+        let objects = self.array(objects, None)?;
+        if objects.is_empty() {
+            return Err(self.error(fields.position, "the scene has no objects"));
+        }
+        for object in objects {
+            let hittable = self.object(object)?;
+            let material = self
+                .object_entries(object)?
+                .iter()
+                .find(|(key, _)| key == "material");
+            if material.is_some_and(|(_, material)| self.emits_light(material)) {
+                // lights are sampled over their surface
+                if hittable.area().is_none() {
+                    return Err(self.error(
+                        object.position,
+                        "moving spheres cannot emit light, they cannot be sampled as lights",
+                    ));
+                }
+                lights.add(hittable.clone());
+            }
+            world.add(hittable);
+        }
+
+        Ok(Scene {
+            world,
+            lights,
+            camera,
+            background,
+        })
+    }
+
+    fn camera(&self, value: &Value) -> Result<CameraSettings, SceneError> {
+        let fields = self.fields(
+            value,
+            &[
+                "lookfrom",
+                "lookat",
+                "vfov",
+                "aperture",
+                "focus_dist",
+                "time0",
+                "time1",
+                "aspect_ratio",
+            ],
+        )?;
+        let lookfrom = self.required_vec3(&fields, "lookfrom")?;
+        let lookat = self.required_vec3(&fields, "lookat")?;
+        let aspect_ratio = self.number_or(&fields, "aspect_ratio", 16.0 / 9.0)?;
+        if aspect_ratio <= 0.0 {
+            return Err(self.error(fields.position, "`aspect_ratio` must be positive"));
+        }
+        Ok(CameraSettings {
+            lookfrom,
+            lookat,
+            vfov: self.number_or(&fields, "vfov", 40.0)?,
+            aperture: self.number_or(&fields, "aperture", 0.0)?,
+            focus_dist: self.number_or(&fields, "focus_dist", (lookfrom - lookat).length())?,
+            time0: self.number_or(&fields, "time0", 0.0)?,
+            time1: self.number_or(&fields, "time1", 1.0)?,
+            aspect_ratio,
+        })
+    }
+
+    fn background(&self, value: &Value) -> Result<Arc<dyn Background>, SceneError> {
+        if let Kind::Array(_) = value.kind {
+            let c = self.vec3(value)?;
+            return Ok(Arc::new(SolidBackground::new(c.x(), c.y(), c.z())));
+        }
+
+        let background: Arc<dyn Background> =
+            match self.type_of(value, &["solid", "gradient", "environment"])? {
+                "solid" => {
+                    let fields = self.fields(value, &["type", "color"])?;
+                    let c = self.required_vec3(&fields, "color")?;
+                    Arc::new(SolidBackground::new(c.x(), c.y(), c.z()))
+                }
+                "gradient" => {
+                    let fields = self.fields(value, &["type", "bottom", "top"])?;
+                    let sky = GradientBackground::sky();
+                    match (fields.get("bottom"), fields.get("top")) {
+                        (None, None) => Arc::new(sky),
+                        (bottom, top) => Arc::new(GradientBackground::new(
+                            bottom.map_or(Ok(Color::new(1.0, 1.0, 1.0)), |v| self.vec3(v))?,
+                            top.map_or(Ok(Color::new(0.5, 0.7, 1.0)), |v| self.vec3(v))?,
+                        )),
+                    }
+                }
+                _ => {
+                    let fields = self.fields(value, &["type", "file", "intensity"])?;
+                    let file = self.required(&fields, "file")?;
+                    let image = Image::load(self.resolve(self.string(file)?))
+                        .map_err(|e| self.error(file.position, &e.to_string()))?;
+                    Arc::new(EnvironmentMap::new(
+                        image,
+                        self.number_or(&fields, "intensity", 1.0)?,
+                    ))
+                }
+            };
+        Ok(background)
+    }
+
+    // A texture given by name, as a color or defined inline.
+    fn texture(&self, value: &Value) -> Result<Box<dyn Texture>, SceneError> {
+        match &value.kind {
+            Kind::String(name) => match self.textures.get(name) {
+                Some(texture) => Ok(Box::new(texture.clone())),
+                None => Err(self.error(value.position, &format!("unknown texture `{}`", name))),
+            },
+            Kind::Array(_) => {
+                let c = self.vec3(value)?;
+                Ok(Box::new(SolidColor::new(c.x(), c.y(), c.z())))
+            }
+            _ => Ok(Box::new(self.texture_definition(value)?)),
+        }
+    }
+
+    fn texture_definition(&self, value: &Value) -> Result<Arc<dyn Texture>, SceneError> {
+        let texture: Arc<dyn Texture> = match self
+            .type_of(value, &["solid", "checker", "image", "noise"])?
+        {
+            "solid" => {
+                let fields = self.fields(value, &["type", "color"])?;
+                let c = self.required_vec3(&fields, "color")?;
+                Arc::new(SolidColor::new(c.x(), c.y(), c.z()))
+            }
+            "checker" => {
+                let fields = self.fields(value, &["type", "odd", "even"])?;
+                Arc::new(Checker::new(
+                    self.texture(self.required(&fields, "odd")?)?,
+                    self.texture(self.required(&fields, "even")?)?,
+                ))
+            }
+            "image" => {
+                let fields = self.fields(value, &["type", "file", "filter", "wrap"])?;
+                let filter = match fields.get("filter") {
+                    None => Filter::Bilinear,
+                    Some(v) => match self.string(v)? {
+                        "nearest" => Filter::Nearest,
+                        "bilinear" => Filter::Bilinear,
+                        other => {
+                            return Err(self.error(
+                                v.position,
+                                &format!(
+                                    "unknown filter `{}`, expected nearest or bilinear",
+                                    other
+                                ),
+                            ))
+                        }
+                    },
+                };
+                let wrap = match fields.get("wrap") {
+                    None => Wrap::Repeat,
+                    Some(v) => match self.string(v)? {
+                        "clamp" => Wrap::Clamp,
+                        "repeat" => Wrap::Repeat,
+                        other => {
+                            return Err(self.error(
+                                v.position,
+                                &format!("unknown wrap mode `{}`, expected clamp or repeat", other),
+                            ))
+                        }
+                    },
+                };
+                let file = self.required(&fields, "file")?;
+                let texture = ImageTexture::load(self.resolve(self.string(file)?), filter, wrap)
+                    .map_err(|e| self.error(file.position, &e.to_string()))?;
+                Arc::new(texture)
+            }
+            _ => {
+                let fields = self.fields(value, &["type", "kind", "scale", "seed"])?;
+                let kind = match fields.get("kind") {
+                    None => NoiseKind::Plain,
+                    Some(v) => match self.string(v)? {
+                        "plain" => NoiseKind::Plain,
+                        "turbulence" => NoiseKind::Turbulence,
+                        "marble" => NoiseKind::Marble,
+                        other => {
+                            return Err(self.error(
+                                v.position,
+                                &format!(
+                                    "unknown noise `{}`, expected plain, turbulence or marble",
+                                    other
+                                ),
+                            ))
+                        }
+                    },
+                };
+                Arc::new(NoiseTexture::new(
+                    self.number_or(&fields, "seed", 0.0)? as u64,
+                    self.number_or(&fields, "scale", 1.0)?,
+                    kind,
+                ))
+            }
+        };
+        Ok(texture)
+    }
+
+    // A material given by name or defined inline.
+    fn material(&self, value: &Value) -> Result<Arc<dyn Material>, SceneError> {
+        match &value.kind {
+            Kind::String(name) => {
+                self.materials.get(name).cloned().ok_or_else(|| {
+                    self.error(value.position, &format!("unknown material `{}`", name))
+                })
+            }
+            _ => self.material_definition(value),
+        }
+    }
+
+    // Whether a valid material, given by name or defined inline, emits light.
+    fn emits_light(&self, value: &Value) -> bool {
+        match &value.kind {
+            Kind::String(name) => self.light_materials.contains(name),
+            _ => self.type_of(value, &["diffuse_light"]).is_ok(),
+        }
+    }
+
+    fn material_definition(&self, value: &Value) -> Result<Arc<dyn Material>, SceneError> {
+        let material: Arc<dyn Material> = match self.type_of(
+            value,
+            &[
+                "lambertian",
+                "metal",
+                "dielectric",
+                "diffuse_light",
+                "isotropic",
+            ],
+        )? {
+            "lambertian" => {
+                let fields = self.fields(value, &["type", "albedo"])?;
+                Arc::new(Lambertian::new(
+                    self.texture(self.required(&fields, "albedo")?)?,
+                ))
+            }
+            "metal" => {
+                let fields = self.fields(value, &["type", "albedo", "fuzz"])?;
+                Arc::new(Metal::new(
+                    self.required_vec3(&fields, "albedo")?,
+                    self.number_or(&fields, "fuzz", 0.0)?,
+                ))
+            }
+            "dielectric" => {
+                let fields = self.fields(value, &["type", "ir"])?;
+                Arc::new(Dielectric::new(self.required_number(&fields, "ir")?))
+            }
+            "diffuse_light" => {
+                let fields = self.fields(value, &["type", "emit"])?;
+                Arc::new(DiffuseLight::new(
+                    self.texture(self.required(&fields, "emit")?)?,
+                ))
+            }
+            _ => {
+                let fields = self.fields(value, &["type", "albedo"])?;
+                Arc::new(Isotropic::new(
+                    self.texture(self.required(&fields, "albedo")?)?,
+                ))
+            }
+        };
+        Ok(material)
+    }
+
+    fn object(&self, value: &Value) -> Result<Arc<dyn Hittable>, SceneError> {
+        let kind = self.type_of(
+            value,
+            &[
+                "sphere",
+                "moving_sphere",
+                "xy_rect",
+                "xz_rect",
+                "yz_rect",
+                "box",
+                "triangle",
+                "mesh",
+                "constant_medium",
+            ],
+        )?;
+        // the fields of each type, besides `type` and `transform`
+        let own_fields: &[&str] = match kind {
+            "sphere" => &["center", "radius", "material"],
+            "moving_sphere" => &["center0", "center1", "time0", "time1", "radius", "material"],
+            "xy_rect" => &["x0", "x1", "y0", "y1", "k", "material"],
+            "xz_rect" => &["x0", "x1", "z0", "z1", "k", "material"],
+            "yz_rect" => &["y0", "y1", "z0", "z1", "k", "material"],
+            "box" => &["min", "max", "material"],
+            "triangle" => &["vertices", "normals", "uvs", "material"],
+            "mesh" => &["file", "material"],
+            _ => &["boundary", "density", "albedo"],
+        };
+        let mut allowed = vec!["type", "transform"];
+        allowed.extend_from_slice(own_fields);
+        let fields = self.fields(value, &allowed)?;
+        let n = |key| self.required_number(&fields, key);
+        let material = || self.material(self.required(&fields, "material")?);
+
+        let object: Arc<dyn Hittable> = match kind {
+            "sphere" => Arc::new(Sphere::new(
+                self.required_vec3(&fields, "center")?,
+                n("radius")?,
+                material()?,
+            )),
+            "moving_sphere" => {
+                let (time0, time1) = (n("time0")?, n("time1")?);
+                // the center moves between the two times
+                if time0 == time1 {
+                    let position = self.required(&fields, "time1")?.position;
+                    return Err(self.error(position, "`time1` must differ from `time0`"));
+                }
+                Arc::new(MovingSphere::new(
+                    self.required_vec3(&fields, "center0")?,
+                    self.required_vec3(&fields, "center1")?,
+                    time0,
+                    time1,
+                    n("radius")?,
+                    material()?,
+                ))
+            }
+            "xy_rect" => {
+                let (x0, x1) = self.bounds(&fields, "x0", "x1")?;
+                let (y0, y1) = self.bounds(&fields, "y0", "y1")?;
+                Arc::new(XYRect::new(x0, x1, y0, y1, n("k")?, material()?))
+            }
+            "xz_rect" => {
+                let (x0, x1) = self.bounds(&fields, "x0", "x1")?;
+                let (z0, z1) = self.bounds(&fields, "z0", "z1")?;
+                Arc::new(XZRect::new(x0, x1, z0, z1, n("k")?, material()?))
+            }
+            "yz_rect" => {
+                let (y0, y1) = self.bounds(&fields, "y0", "y1")?;
+                let (z0, z1) = self.bounds(&fields, "z0", "z1")?;
+                Arc::new(YZRect::new(y0, y1, z0, z1, n("k")?, material()?))
+            }
+            "box" => {
+                let (min, max) = (
+                    self.required_vec3(&fields, "min")?,
+                    self.required_vec3(&fields, "max")?,
+                );
+                if (0..3).any(|i| min[i] > max[i]) {
+                    let position = self.required(&fields, "max")?.position;
+                    return Err(self.error(position, "`max` must not be below `min`"));
+                }
+                Arc::new(BoxShape::new(min, max, material()?))
+            }
+            "triangle" => {
+                let vec3s = |v: &Value| -> Result<[Vec3; 3], SceneError> {
+                    let items = self.array(v, Some(3))?;
+                    Ok([
+                        self.vec3(&items[0])?,
+                        self.vec3(&items[1])?,
+                        self.vec3(&items[2])?,
+                    ])
+                };
+                let uv = |v: &Value| -> Result<(f64, f64), SceneError> {
+                    let items = self.array(v, Some(2))?;
+                    Ok((self.number(&items[0])?, self.number(&items[1])?))
+                };
+                let uvs = match fields.get("uvs") {
+                    Some(v) => {
+                        let items = self.array(v, Some(3))?;
+                        Some([uv(&items[0])?, uv(&items[1])?, uv(&items[2])?])
+                    }
+                    None => None,
+                };
+                Arc::new(Triangle::new(
+                    vec3s(self.required(&fields, "vertices")?)?,
+                    fields.get("normals").map(vec3s).transpose()?,
+                    uvs,
+                    material()?,
+                ))
+            }
+            "mesh" => {
+                // faces without a material in the OBJ file get a neutral gray
+                let default_material = match fields.get("material") {
+                    Some(v) => self.material(v)?,
+                    None => Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5)))),
+                };
+                let file = self.required(&fields, "file")?;
+                obj::load_obj(self.resolve(self.string(file)?), default_material)
+                    .map_err(|e| self.error(file.position, &e.to_string()))?
+            }
+            _ => {
+                let density = n("density")?;
+                if !(density > 0.0 && density.is_finite()) {
+                    let position = self.required(&fields, "density")?.position;
+                    return Err(self.error(position, "`density` must be positive"));
+                }
+                Arc::new(ConstantMedium::new(
+                    self.object(self.required(&fields, "boundary")?)?,
+                    density,
+                    self.texture(self.required(&fields, "albedo")?)?,
+                ))
+            }
+        };
+
+        match fields.get("transform") {
+            Some(transform) => self.transform(object, transform),
+            None => Ok(object),
+        }
+    }
+
+    // Applies a list of transformations, in order, such as
+    // `[{"scale": 2}, {"rotate_y": 15}, {"translate": [1, 0, 0]}]`.
+    fn transform(
+        &self,
+        mut object: Arc<dyn Hittable>,
+        value: &Value,
+    ) -> Result<Arc<dyn Hittable>, SceneError> {
+        for step in self.array(value, None)? {
+            let fields = self.fields(
+                step,
+                &[
+                    "translate",
+                    "rotate_x",
+                    "rotate_y",
+                    "rotate_z",
+                    "scale",
+                    "matrix",
+                ],
+            )?;
+            let (key, v) = match fields.entries {
+                [(key, v)] => (key.as_str(), v),
+                _ => {
+                    return Err(self.error(
+                        step.position,
+                        "expected exactly one transformation per step",
+                    ))
+                }
+            };
+            object = match key {
+                "translate" => Arc::new(Translate::new(object, self.vec3(v)?)),
+                "rotate_x" => Arc::new(RotateX::new(object, self.number(v)?)),
+                "rotate_y" => Arc::new(RotateY::new(object, self.number(v)?)),
+                "rotate_z" => Arc::new(RotateZ::new(object, self.number(v)?)),
+                "scale" => {
+                    let factor = match v.kind {
+                        Kind::Number(s) => Vec3::new(s, s, s),
+                        _ => self.vec3(v)?,
+                    };
+                    let matrix = Matrix4::scale(factor);
+                    if matrix.inverse().is_none() {
+                        return Err(self.error(v.position, "the scale is not invertible"));
+                    }
+                    Arc::new(Transform::new(object, matrix))
+                }
+                _ => {
+                    let rows = self.array(v, Some(4))?;
+                    let mut m = [[0.0; 4]; 4];
+                    for (row, value) in m.iter_mut().zip(rows) {
+                        let items = self.array(value, Some(4))?;
+                        for (x, item) in row.iter_mut().zip(items) {
+                            *x = self.number(item)?;
+                        }
+                    }
+                    let matrix = Matrix4::new(m);
+                    if matrix.inverse().is_none() {
+                        return Err(self.error(v.position, "the matrix is not invertible"));
+                    }
+                    Arc::new(Transform::new(object, matrix))
+                }
+            };
+        }
+        Ok(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads a scene with a camera, a `gray` and a `light` material, and `object` on line 5.
+    fn load(test: &str, object: &str) -> Result<Scene, SceneError> {
+        let source = format!(
+            "{{\n  \"camera\": {{\"lookfrom\": [0, 0, 5], \"lookat\": [0, 0, 0]}},\n  \"materials\": {{\"gray\": {{\"type\": \"lambertian\", \"albedo\": [0.5, 0.5, 0.5]}}, \"light\": {{\"type\": \"diffuse_light\", \"emit\": [4, 4, 4]}}}},\n  \"objects\": [\n{}\n]}}\n",
+            object
+        );
+        let path = std::env::temp_dir().join(format!(
+            "raytracing-scene-{}-{}.json",
+            test,
+            std::process::id()
+        ));
+        fs::write(&path, source).unwrap();
+        let result = Scene::load(&path);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    // The line, column and message of the error in `object`, expecting it where `at` starts.
+    fn assert_error(test: &str, object: &str, at: &str, message: &str) {
+        let e = match load(test, object) {
+            Err(e) => e,
+            Ok(_) => panic!("expected an error loading {}", object),
+        };
+        let column = object.find(at).unwrap() + 1;
+        assert_eq!(e.position, Some(Position { line: 5, column }));
+        assert_eq!(e.message, message);
+    }
+
+    #[test]
+    fn lights_are_registered() {
+        let scene = load(
+            "lights",
+            "{\"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"gray\"},\n{\"type\": \"xy_rect\", \"x0\": 0, \"x1\": 2, \"y0\": 0, \"y1\": 3, \"k\": 1, \"material\": \"light\", \"transform\": [{\"scale\": 2}]}",
+        )
+        .unwrap();
+        assert_eq!(scene.world.area().map(|a| a.round()), Some(37.0));
+        assert_eq!(scene.lights.area().map(|a| a.round()), Some(24.0));
+        assert_eq!(scene.camera.aspect_ratio, 16.0 / 9.0);
+    }
+
+    #[test]
+    fn json_errors_keep_their_position() {
+        assert_error(
+            "syntax",
+            "{\"type\": \"sphere\" \"radius\": 1}",
+            "\"radius\"",
+            "expected `,` or `}`",
+        );
+    }
+
+    #[test]
+    fn unknown_names() {
+        assert_error(
+            "type",
+            "{\"type\": \"cone\"}",
+            "{",
+            "unknown type `cone`, expected one of: sphere, moving_sphere, xy_rect, xz_rect, yz_rect, box, triangle, mesh, constant_medium",
+        );
+        assert_error(
+            "field",
+            "{\"type\": \"sphere\", \"centre\": [0, 0, 0]}",
+            "[",
+            "unknown field `centre`, expected one of: type, transform, center, radius, material",
+        );
+        assert_error(
+            "material",
+            "{\"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"red\"}",
+            "\"red\"",
+            "unknown material `red`",
+        );
+        assert_error(
+            "missing",
+            "{\"type\": \"sphere\", \"center\": [0, 0, 0], \"material\": \"gray\"}",
+            "{",
+            "missing field `radius`",
+        );
+        assert_error(
+            "vector",
+            "{\"type\": \"sphere\", \"center\": [0, 0], \"radius\": 1, \"material\": \"gray\"}",
+            "[",
+            "expected an array of 3 elements, found an array",
+        );
+    }
+
+    #[test]
+    fn degenerate_objects() {
+        assert_error(
+            "moving",
+            "{\"type\": \"moving_sphere\", \"center0\": [0, 0, 0], \"center1\": [1, 0, 0], \"time0\": 0.5, \"time1\": 0.5, \"radius\": 1, \"material\": \"gray\"}",
+            "0.5, \"radius",
+            "`time1` must differ from `time0`",
+        );
+        assert_error(
+            "box",
+            "{\"type\": \"box\", \"min\": [0, 2, 0], \"max\": [1, 1, 1], \"material\": \"gray\"}",
+            "[1, 1, 1]",
+            "`max` must not be below `min`",
+        );
+        assert_error(
+            "rect",
+            "{\"type\": \"xz_rect\", \"x0\": 0, \"x1\": 1, \"z0\": 2, \"z1\": -2, \"k\": 0, \"material\": \"gray\"}",
+            "-2",
+            "`z1` must be above `z0`",
+        );
+        assert_error(
+            "flatrect",
+            "{\"type\": \"yz_rect\", \"y0\": 1, \"y1\": 1, \"z0\": 0, \"z1\": 1, \"k\": 0, \"material\": \"light\"}",
+            "1, \"z0",
+            "`y1` must be above `y0`",
+        );
+        for density in ["0", "-1"] {
+            let object = format!(
+                "{{\"type\": \"constant_medium\", \"boundary\": {{\"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"gray\"}}, \"albedo\": [1, 1, 1], \"density\": {}}}",
+                density
+            );
+            assert_error(
+                "medium",
+                &object,
+                &format!("{}}}", density),
+                "`density` must be positive",
+            );
+        }
+    }
+
+    #[test]
+    fn singular_transforms() {
+        assert_error(
+            "scale",
+            "{\"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"gray\", \"transform\": [{\"scale\": 1e-13}]}",
+            "1e-13",
+            "the scale is not invertible",
+        );
+        assert_error(
+            "matrix",
+            "{\"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"gray\", \"transform\": [{\"matrix\": [[1, 0, 0, 0], [1, 0, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]}]}",
+            "[[",
+            "the matrix is not invertible",
+        );
+    }
+
+    #[test]
+    fn lights_must_have_an_area() {
+        assert_error(
+            "movinglight",
+            "{\"type\": \"moving_sphere\", \"center0\": [0, 0, 0], \"center1\": [1, 0, 0], \"time0\": 0, \"time1\": 1, \"radius\": 1, \"material\": \"light\"}",
+            "{",
+            "moving spheres cannot emit light, they cannot be sampled as lights",
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        let e = match Scene::load("/nonexistent/scene.json") {
+            Err(e) => e,
+            Ok(_) => panic!("expected an error"),
+        };
+        assert_eq!(e.position, None);
+    }
+}
@@ -0,0 +1,328 @@
+use std::sync::Arc;
+
+use raytracing::background::{GradientBackground, SolidBackground};
+use raytracing::hit::{Hittable, HittableList};
+use raytracing::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use raytracing::medium::ConstantMedium;
+use raytracing::rect::{BoxShape, XYRect, XZRect, YZRect};
+use raytracing::scene::{CameraSettings, Scene};
+use raytracing::sphere::{MovingSphere, Sphere};
+use raytracing::texture::{Checker, NoiseKind, NoiseTexture, SolidColor};
+use raytracing::transform::{RotateY, Translate};
+use raytracing::vec3::{Color, Point3, Vec3};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// The names of the built-in scenes, in the order they are listed in the help.
+pub const NAMES: [&str; 5] = [
+    "random",
+    "simple-light",
+    "two-perlin-spheres",
+    "cornell-box",
+    "cornell-smoke",
+];
+
+// Builds a built-in scene by name, `seed` drives any randomness in its construction.
+pub fn build(name: &str, seed: u64) -> Option<Scene> {
+    let outdoor = |lookfrom, lookat, aperture| CameraSettings {
+        lookfrom,
+        lookat,
+        vfov: 20.0,
+        aperture,
+        focus_dist: 10.0,
+        time0: 0.0,
+        time1: 1.0,
+        aspect_ratio: 16.0 / 9.0,
+    };
+    let cornell = || CameraSettings {
+        lookfrom: Point3::new(278.0, 278.0, -800.0),
+        lookat: Point3::new(278.0, 278.0, 0.0),
+        vfov: 40.0,
+        aperture: 0.0,
+        focus_dist: 10.0,
+        time0: 0.0,
+        time1: 1.0,
+        aspect_ratio: 1.0,
+    };
+
+    let scene = match name {
+        "random" => Scene {
+            world: random_scene(seed),
+            lights: HittableList::default(),
+            camera: outdoor(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 0.1),
+            background: Arc::new(GradientBackground::sky()),
+        },
+        "simple-light" => {
+            let (world, lights) = simple_light();
+            Scene {
+                world,
+                lights,
+                camera: outdoor(Point3::new(26.0, 3.0, 6.0), Point3::new(0.0, 2.0, 0.0), 0.0),
+                background: Arc::new(SolidBackground::new(0.0, 0.0, 0.0)),
+            }
+        }
+        "two-perlin-spheres" => Scene {
+            world: two_perlin_spheres(seed),
+            lights: HittableList::default(),
+            camera: outdoor(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 0.0),
+            background: Arc::new(GradientBackground::sky()),
+        },
+        "cornell-box" => {
+            let (world, lights) = cornell_box();
+            Scene {
+                world,
+                lights,
+                camera: cornell(),
+                background: Arc::new(SolidBackground::new(0.0, 0.0, 0.0)),
+            }
+        }
+        "cornell-smoke" => {
+            let (world, lights) = cornell_smoke();
+            Scene {
+                world,
+                lights,
+                camera: cornell(),
+                background: Arc::new(SolidBackground::new(0.0, 0.0, 0.0)),
+            }
+        }
+        _ => return None,
+    };
+    Some(scene)
+}
+
+fn random_scene(seed: u64) -> HittableList {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world = HittableList::default();
+
+    let checker = Checker::new(
+        Box::new(SolidColor::new(0.2, 0.3, 0.1)),
+        Box::new(SolidColor::new(0.9, 0.9, 0.9)),
+    );
+    let ground_material = Arc::new(Lambertian::new(Box::new(checker)));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        ground_material,
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = rng.gen::<f64>();
+            let center = Point3::new(
+                a as f64 + 0.9 * rng.gen::<f64>(),
+                0.2,
+                b as f64 + 0.9 * rng.gen::<f64>(),
+            );
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.0 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    world.add(Arc::new(Sphere::new(
+                        center,
+                        0.2,
+                        Arc::new(Lambertian::new(Box::new(SolidColor::new(
+                            rng.gen::<f64>(),
+                            rng.gen::<f64>(),
+                            rng.gen::<f64>(),
+                        )))),
+                    )));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    world.add(Arc::new(MovingSphere::new(
+                        center,
+                        center + Vec3::new(0.0, rng.gen_range(0.0..0.5), 0.0),
+                        0.0,
+                        1.0,
+                        0.2,
+                        Arc::new(Metal::new(
+                            Color::new(
+                                rng.gen_range(0.5..1.0),
+                                rng.gen_range(0.5..1.0),
+                                rng.gen_range(0.5..1.0),
+                            ),
+                            rng.gen_range(0.0..0.5) / 2.0,
+                        )),
+                    )));
+                } else {
+                    world.add(Arc::new(Sphere::new(
+                        center,
+                        0.2,
+                        Arc::new(Dielectric::new(1.5)),
+                    )));
+                }
+            }
+        }
+    }
+
+    world.add(Arc::new(Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::new(Box::new(SolidColor::new(0.4, 0.2, 0.1)))),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+    )));
+
+    world
+}
+
+fn two_perlin_spheres(seed: u64) -> HittableList {
+    let mut world = HittableList::default();
+
+    let marble = Arc::new(Lambertian::new(Box::new(NoiseTexture::new(
+        seed,
+        4.0,
+        NoiseKind::Marble,
+    ))));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        marble.clone(),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        marble,
+    )));
+
+    world
+}
+
+// The world and its lights.
+fn simple_light() -> (HittableList, HittableList) {
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    let checker = Checker::new(
+        Box::new(SolidColor::new(0.2, 0.3, 0.1)),
+        Box::new(SolidColor::new(0.9, 0.9, 0.9)),
+    );
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Box::new(checker))),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Arc::new(Lambertian::new(Box::new(SolidColor::new(0.4, 0.2, 0.1)))),
+    )));
+
+    let light = Arc::new(DiffuseLight::new(Box::new(SolidColor::new(4.0, 4.0, 4.0))));
+    let rect: Arc<dyn Hittable> = Arc::new(XYRect::new(3.0, 5.0, 1.0, 3.0, -2.0, light.clone()));
+    let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0.0, 7.0, 0.0), 2.0, light));
+    for object in [rect, sphere] {
+        world.add(object.clone());
+        lights.add(object);
+    }
+
+    (world, lights)
+}
+
+// The walls and light of the Cornell box, with the standard materials. The light is also added to
+// `lights`.
+fn cornell_box_walls(
+    world: &mut HittableList,
+    lights: &mut HittableList,
+    light: f64,
+) -> Arc<Lambertian> {
+    let red = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.65, 0.05, 0.05))));
+    let white = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.73, 0.73, 0.73))));
+    let green = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.12, 0.45, 0.15))));
+    let light = Arc::new(DiffuseLight::new(Box::new(SolidColor::new(
+        light, light, light,
+    ))));
+
+    world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 555.0, green)));
+    world.add(Arc::new(YZRect::new(0.0, 555.0, 0.0, 555.0, 0.0, red)));
+    let light: Arc<dyn Hittable> = Arc::new(XZRect::new(213.0, 343.0, 227.0, 332.0, 554.0, light));
+    world.add(light.clone());
+    lights.add(light);
+    world.add(Arc::new(XZRect::new(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        0.0,
+        white.clone(),
+    )));
+    world.add(Arc::new(XZRect::new(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        white.clone(),
+    )));
+    world.add(Arc::new(XYRect::new(
+        0.0,
+        555.0,
+        0.0,
+        555.0,
+        555.0,
+        white.clone(),
+    )));
+
+    white
+}
+
+// The tall and the short box standing in the Cornell box.
+fn cornell_box_boxes(white: Arc<Lambertian>) -> (Arc<dyn Hittable>, Arc<dyn Hittable>) {
+    let box1 = Arc::new(BoxShape::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 330.0, 165.0),
+        white.clone(),
+    ));
+    let box1 = Arc::new(RotateY::new(box1, 15.0));
+    let box1 = Arc::new(Translate::new(box1, Vec3::new(265.0, 0.0, 295.0)));
+
+    let box2 = Arc::new(BoxShape::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 165.0, 165.0),
+        white,
+    ));
+    let box2 = Arc::new(RotateY::new(box2, -18.0));
+    let box2 = Arc::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
+
+    (box1, box2)
+}
+
+fn cornell_box() -> (HittableList, HittableList) {
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    let white = cornell_box_walls(&mut world, &mut lights, 15.0);
+    let (box1, box2) = cornell_box_boxes(white);
+    world.add(box1);
+    world.add(box2);
+
+    (world, lights)
+}
+
+fn cornell_smoke() -> (HittableList, HittableList) {
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+
+    let white = cornell_box_walls(&mut world, &mut lights, 7.0);
+    let (box1, box2) = cornell_box_boxes(white);
+    world.add(Arc::new(ConstantMedium::new(
+        box1,
+        0.01,
+        Box::new(SolidColor::new(0.0, 0.0, 0.0)),
+    )));
+    world.add(Arc::new(ConstantMedium::new(
+        box2,
+        0.01,
+        Box::new(SolidColor::new(1.0, 1.0, 1.0)),
+    )));
+
+    (world, lights)
+}
@@ -1,11 +1,14 @@
+use std::f64::consts::PI;
 use std::ops::RangeInclusive;
 use std::sync::Arc;
 
 use crate::aabb::AABB;
 use crate::hit::{HitRecord, Hittable, Normal};
 use crate::material::Material;
+use crate::onb::Onb;
 use crate::ray::Ray;
-use crate::util::dot;
+use crate::sampler::Sampler;
+use crate::util::{dot, random_unit_vector};
 use crate::vec3::{Point3, Vec3};
 
 fn get_sphere_uv(p: &Point3) -> (f64, f64) {
@@ -31,10 +34,25 @@ impl Sphere {
             material,
         }
     }
+
+    // The cosine of the half angle of the cone the sphere fills seen from the point, `None` if
+    // the point is inside.
+    fn cos_theta_max(&self, origin: &Point3) -> Option<f64> {
+        let distance_squared = (self.center - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return None;
+        }
+        Some((1.0 - self.radius * self.radius / distance_squared).sqrt())
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_range: &RangeInclusive<f64>) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
         let origin = ray.origin();
         let direction = ray.direction();
 
@@ -79,6 +97,38 @@ impl Hittable for Sphere {
             self.center + Vec3::new(self.radius, self.radius, self.radius),
         ))
     }
+
+    fn area(&self) -> Option<f64> {
+        Some(4.0 * PI * self.radius * self.radius)
+    }
+
+    // From outside, the directions are picked uniformly in the cone around the sphere, from
+    // inside in all directions.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        if self
+            .hit(&ray, &RangeInclusive::new(0.001, f64::INFINITY), sampler)
+            .is_none()
+        {
+            return 0.0;
+        }
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => 1.0 / (4.0 * PI),
+        }
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return random_unit_vector(sampler),
+        };
+        let (r1, r2) = sampler.get_2d();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        Onb::new(&(self.center - *origin)).local(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+    }
 }
 
 pub struct MovingSphere {
@@ -116,7 +166,12 @@ impl MovingSphere {
 }
 
 impl Hittable for MovingSphere {
-    fn hit(&self, ray: &Ray, t_range: &RangeInclusive<f64>) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
         let origin = ray.origin();
         let direction = ray.direction();
 
@@ -1,3 +1,8 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::image::{Filter, Image, ImageError, Wrap};
+use crate::noise::Perlin;
 use crate::vec3::{Color, Point3};
 
 pub trait Texture: Send + Sync {
@@ -17,7 +22,7 @@ impl SolidColor {
 }
 
 impl Texture for SolidColor {
-    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         self.color
     }
 }
@@ -43,3 +48,76 @@ impl Texture for Checker {
         }
     }
 }
+
+pub struct ImageTexture {
+    image: Image,
+    filter: Filter,
+    wrap: Wrap,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image, filter: Filter, wrap: Wrap) -> Self {
+        Self {
+            image,
+            filter,
+            wrap,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, filter: Filter, wrap: Wrap) -> Result<Self, ImageError> {
+        Ok(Self::new(Image::load(path)?, filter, wrap))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        self.image.sample(u, v, self.filter, self.wrap)
+    }
+}
+
+const TURBULENCE_DEPTH: usize = 7;
+
+pub enum NoiseKind {
+    Plain,
+    Turbulence,
+    // veins of turbulence along the z axis
+    Marble,
+}
+
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+    kind: NoiseKind,
+}
+
+impl NoiseTexture {
+    pub fn new(seed: u64, scale: f64, kind: NoiseKind) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            kind,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let scaled = *p * self.scale;
+        let intensity = match self.kind {
+            NoiseKind::Plain => 0.5 * (1.0 + self.noise.noise(&scaled)),
+            NoiseKind::Turbulence => self.noise.turb(&scaled, TURBULENCE_DEPTH),
+            // the scale only sets the frequency of the stripes, not of the turbulence
+            NoiseKind::Marble => {
+                0.5 * (1.0 + f64::sin(scaled.z() + 10.0 * self.noise.turb(p, TURBULENCE_DEPTH)))
+            }
+        };
+        Color::new(1.0, 1.0, 1.0) * intensity
+    }
+}
+
+// Lets a texture be shared, e.g. by several materials of a scene file.
+impl<T: Texture + ?Sized> Texture for Arc<T> {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        (**self).value(u, v, p)
+    }
+}
@@ -0,0 +1,106 @@
+// The color pipeline from the linear framebuffer to 8-bit output: exposure, tone mapping,
+// the sRGB transfer function, clamping and dithering.
+
+use crate::vec3::Color;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    // values above 1 are clipped
+    Clamp,
+    // Reinhard's L / (1 + L) on the luminance, keeping the hue
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(self, color: Color) -> Color {
+        match self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard => {
+                let l = luminance(color);
+                if l <= 0.0 {
+                    color
+                } else {
+                    color * (1.0 / (1.0 + l))
+                }
+            }
+            ToneMap::Aces => {
+                let curve = |x: f64| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                Color::new(curve(color.x()), curve(color.y()), curve(color.z()))
+            }
+        }
+    }
+}
+
+// Relative luminance of a linear Rec. 709 color.
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+// The sRGB transfer function, the inverse of `image::srgb_to_linear`.
+pub fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// A 4x4 Bayer matrix, its thresholds spread evenly over [0, 1).
+const BAYER: [[f64; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct ColorPipeline {
+    // in stops, each one doubles the brightness
+    exposure: f64,
+    tone_map: ToneMap,
+    // ordered dithering hides the banding of smooth gradients in 8 bits
+    dither: bool,
+}
+
+impl ColorPipeline {
+    pub fn new(exposure: f64, tone_map: ToneMap, dither: bool) -> Self {
+        Self {
+            exposure,
+            tone_map,
+            dither,
+        }
+    }
+
+    // The display color of a linear color, each component in [0, 1].
+    pub fn display(&self, color: Color) -> Color {
+        let exposed = color * 2.0_f64.powf(self.exposure);
+        let mapped = self.tone_map.apply(exposed);
+        // NaN, from a broken sample, is shown as black rather than spreading
+        let encode = |c: f64| {
+            if c.is_nan() {
+                0.0
+            } else {
+                linear_to_srgb(c.clamp(0.0, 1.0))
+            }
+        };
+        Color::new(encode(mapped.x()), encode(mapped.y()), encode(mapped.z()))
+    }
+
+    // Quantizes the color of the pixel (x, y) to 8 bits per channel.
+    pub fn to_rgb8(&self, color: Color, x: usize, y: usize) -> [u8; 3] {
+        let display = self.display(color);
+        let offset = if self.dither {
+            BAYER[y % 4][x % 4] / 16.0
+        } else {
+            0.5
+        };
+        let quantize = |c: f64| (255.0 * c + offset).clamp(0.0, 255.0) as u8;
+        [
+            quantize(display.x()),
+            quantize(display.y()),
+            quantize(display.z()),
+        ]
+    }
+}
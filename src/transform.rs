@@ -0,0 +1,629 @@
+use std::ops::{Mul, RangeInclusive};
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hit::{HitRecord, Hittable, Normal};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::{Point3, Vec3};
+
+// The eight corners of a bounding box.
+fn corners(bbox: &AABB) -> impl Iterator<Item = Point3> + '_ {
+    (0..8).map(move |i| {
+        let pick = |bit: usize, axis: usize| {
+            if i & bit == 0 {
+                bbox.minimum[axis]
+            } else {
+                bbox.maximum[axis]
+            }
+        };
+        Point3::new(pick(1, 0), pick(2, 1), pick(4, 2))
+    })
+}
+
+// The axis aligned box enclosing all the points.
+fn enclosing_box(points: impl Iterator<Item = Point3>) -> AABB {
+    let mut minimum = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut maximum = Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+    for p in points {
+        for i in 0..3 {
+            minimum[i] = minimum[i].min(p[i]);
+            maximum[i] = maximum[i].max(p[i]);
+        }
+    }
+    AABB::new(minimum, maximum)
+}
+
+fn map_normal(normal: &Normal, f: impl Fn(Vec3) -> Vec3) -> Normal {
+    match normal {
+        Normal::Front(n) => Normal::Front(f(*n)),
+        Normal::Back(n) => Normal::Back(f(*n)),
+    }
+}
+
+pub struct Translate {
+    object: Arc<dyn Hittable>,
+    offset: Vec3,
+}
+
+impl Translate {
+    pub fn new(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
+        Self { object, offset }
+    }
+}
+
+impl Hittable for Translate {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let moved = Ray::new(ray.origin() - self.offset, ray.direction(), ray.time());
+        let mut hit = self.object.hit(&moved, t_range, sampler)?;
+        hit.point += self.offset;
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let bbox = self.object.bounding_box()?;
+        Some(AABB::new(
+            bbox.minimum + self.offset,
+            bbox.maximum + self.offset,
+        ))
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.object.area()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.object
+            .pdf_value(&(*origin - self.offset), direction, sampler)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random(&(*origin - self.offset), sampler)
+    }
+}
+
+// A rotation about one of the coordinate axes, shared by `RotateX`, `RotateY` and `RotateZ`.
+struct Rotate {
+    object: Arc<dyn Hittable>,
+    axis: usize,
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: Option<AABB>,
+}
+
+impl Rotate {
+    fn new(object: Arc<dyn Hittable>, axis: usize, angle: f64) -> Self {
+        let radians = angle.to_radians();
+        let mut rotate = Self {
+            object,
+            axis,
+            sin_theta: radians.sin(),
+            cos_theta: radians.cos(),
+            bbox: None,
+        };
+        rotate.bbox = rotate
+            .object
+            .bounding_box()
+            .map(|bbox| enclosing_box(corners(&bbox).map(|p| rotate.rotate(p, 1.0))));
+        rotate
+    }
+
+    // Rotates `v` by the angle around the axis, or by the opposite angle if `sign` is -1.
+    fn rotate(&self, v: Vec3, sign: f64) -> Vec3 {
+        let (b, c) = ((self.axis + 1) % 3, (self.axis + 2) % 3);
+        let sin_theta = sign * self.sin_theta;
+        let mut rotated = v;
+        rotated[b] = self.cos_theta * v[b] - sin_theta * v[c];
+        rotated[c] = sin_theta * v[b] + self.cos_theta * v[c];
+        rotated
+    }
+
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let rotated = Ray::new(
+            self.rotate(ray.origin(), -1.0),
+            self.rotate(ray.direction(), -1.0),
+            ray.time(),
+        );
+        let mut hit = self.object.hit(&rotated, t_range, sampler)?;
+        hit.point = self.rotate(hit.point, 1.0);
+        hit.normal = map_normal(&hit.normal, |n| self.rotate(n, 1.0));
+        Some(hit)
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.object.area()
+    }
+
+    // Rotations keep solid angles.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.object.pdf_value(
+            &self.rotate(*origin, -1.0),
+            &self.rotate(*direction, -1.0),
+            sampler,
+        )
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.object.random(&self.rotate(*origin, -1.0), sampler);
+        self.rotate(direction, 1.0)
+    }
+}
+
+pub struct RotateX(Rotate);
+
+impl RotateX {
+    // `angle` is in degrees
+    pub fn new(object: Arc<dyn Hittable>, angle: f64) -> Self {
+        Self(Rotate::new(object, 0, angle))
+    }
+}
+
+impl Hittable for RotateX {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        self.0.hit(ray, t_range, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.0.bbox.clone()
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.0.area()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.0.pdf_value(origin, direction, sampler)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.0.random(origin, sampler)
+    }
+}
+
+pub struct RotateY(Rotate);
+
+impl RotateY {
+    // `angle` is in degrees
+    pub fn new(object: Arc<dyn Hittable>, angle: f64) -> Self {
+        Self(Rotate::new(object, 1, angle))
+    }
+}
+
+impl Hittable for RotateY {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        self.0.hit(ray, t_range, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.0.bbox.clone()
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.0.area()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.0.pdf_value(origin, direction, sampler)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.0.random(origin, sampler)
+    }
+}
+
+pub struct RotateZ(Rotate);
+
+impl RotateZ {
+    // `angle` is in degrees
+    pub fn new(object: Arc<dyn Hittable>, angle: f64) -> Self {
+        Self(Rotate::new(object, 2, angle))
+    }
+}
+
+impl Hittable for RotateZ {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        self.0.hit(ray, t_range, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.0.bbox.clone()
+    }
+
+    fn area(&self) -> Option<f64> {
+        self.0.area()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        self.0.pdf_value(origin, direction, sampler)
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.0.random(origin, sampler)
+    }
+}
+
+// A row-major affine transformation matrix acting on column vectors.
+#[derive(Clone, Copy, Debug)]
+pub struct Matrix4([[f64; 4]; 4]);
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Matrix4(m)
+    }
+
+    pub fn identity() -> Self {
+        Matrix4::scale(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Matrix4([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(factor: Vec3) -> Self {
+        Matrix4([
+            [factor.x(), 0.0, 0.0, 0.0],
+            [0.0, factor.y(), 0.0, 0.0],
+            [0.0, 0.0, factor.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // `angle` is in degrees
+    pub fn rotation_x(angle: f64) -> Self {
+        let (s, c) = angle.to_radians().sin_cos();
+        Matrix4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, c, -s, 0.0],
+            [0.0, s, c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // `angle` is in degrees
+    pub fn rotation_y(angle: f64) -> Self {
+        let (s, c) = angle.to_radians().sin_cos();
+        Matrix4([
+            [c, 0.0, s, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-s, 0.0, c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // `angle` is in degrees
+    pub fn rotation_z(angle: f64) -> Self {
+        let (s, c) = angle.to_radians().sin_cos();
+        Matrix4([
+            [c, -s, 0.0, 0.0],
+            [s, c, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+        Matrix4(m)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.0;
+        let mut inv = Matrix4::identity().0;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1E-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix4(inv))
+    }
+
+    // The determinant of the linear part, the upper left 3x3 block.
+    pub fn determinant(&self) -> f64 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.0;
+        Point3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Matrix4(m)
+    }
+}
+
+// Places an object with an arbitrary affine transformation, mapping object space to world space.
+pub struct Transform {
+    object: Arc<dyn Hittable>,
+    matrix: Matrix4,
+    inverse: Matrix4,
+    // transforms normals, the transpose of the inverse
+    normal_matrix: Matrix4,
+    // how much the matrix scales volumes
+    determinant: f64,
+    bbox: Option<AABB>,
+}
+
+impl Transform {
+    // Panics if the matrix is not invertible.
+    pub fn new(object: Arc<dyn Hittable>, matrix: Matrix4) -> Self {
+        let inverse = matrix.inverse().expect("transform matrix is singular");
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| enclosing_box(corners(&bbox).map(|p| matrix.transform_point(p))));
+        Self {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            determinant: matrix.determinant().abs(),
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        // The direction is not renormalized so that t is the same in both spaces.
+        let local = Ray::new(
+            self.inverse.transform_point(ray.origin()),
+            self.inverse.transform_vector(ray.direction()),
+            ray.time(),
+        );
+        let mut hit = self.object.hit(&local, t_range, sampler)?;
+        hit.point = self.matrix.transform_point(hit.point);
+        hit.normal = map_normal(&hit.normal, |n| {
+            self.normal_matrix.transform_vector(n).normalize()
+        });
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bbox.clone()
+    }
+
+    // Exact for uniform scales. Otherwise it is only a typical scale of the areas, which is enough
+    // to weigh the object against others.
+    fn area(&self) -> Option<f64> {
+        Some(self.object.area()? * self.determinant.powf(2.0 / 3.0))
+    }
+
+    // The matrix changes solid angles: a small cone around a direction d in object space is
+    // stretched by |det M| / |M d|^3, for d of unit length.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        let local = self.inverse.transform_vector(direction.normalize());
+        let pdf = self
+            .object
+            .pdf_value(&self.inverse.transform_point(*origin), &local, sampler);
+        pdf / (self.determinant * local.length().powi(3))
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let local = self
+            .object
+            .random(&self.inverse.transform_point(*origin), sampler);
+        self.matrix.transform_vector(local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use crate::util::{cross, dot, random_unit_vector};
+    use std::f64::consts::PI;
+
+    fn composed() -> Matrix4 {
+        Matrix4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Matrix4::rotation_y(30.0)
+            * Matrix4::rotation_x(-50.0)
+            * Matrix4::scale(Vec3::new(2.0, 1.0, 0.5))
+    }
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        let material = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5))));
+        Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, material))
+    }
+
+    #[test]
+    fn inverse() {
+        let m = composed();
+        for product in [m * m.inverse().unwrap(), m.inverse().unwrap() * m] {
+            for i in 0..4 {
+                for j in 0..4 {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    assert!((product.0[i][j] - expected).abs() < 1e-12, "{:?}", product);
+                }
+            }
+        }
+        assert!((m.determinant() - 1.0).abs() < 1e-12);
+        let p = Point3::new(0.3, -4.0, 7.0);
+        let back = m.inverse().unwrap().transform_point(m.transform_point(p));
+        assert!((back - p).length() < 1e-12);
+    }
+
+    #[test]
+    fn singular_matrices() {
+        assert!(Matrix4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+        assert!(Matrix4::scale(Vec3::new(1e-13, 1e-13, 1e-13))
+            .inverse()
+            .is_none());
+        let rank_two = Matrix4::new([
+            [1.0, 2.0, 3.0, 0.0],
+            [2.0, 4.0, 6.0, 1.0],
+            [0.0, 1.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert!(rank_two.inverse().is_none());
+    }
+
+    #[test]
+    fn transformed_normals() {
+        let matrix = composed();
+        let object = Transform::new(unit_sphere(), matrix);
+        let center = matrix.transform_point(Point3::new(0.0, 0.0, 0.0));
+        let mut sampler = IndependentSampler::new(3);
+        for _ in 0..100 {
+            // from outside, towards the center
+            let origin = center + random_unit_vector(&mut sampler) * 10.0;
+            let ray = Ray::new(origin, center - origin, 0.0);
+            let hit = object
+                .hit(
+                    &ray,
+                    &RangeInclusive::new(0.001, f64::INFINITY),
+                    &mut sampler,
+                )
+                .unwrap();
+            let normal = match hit.normal {
+                Normal::Front(n) => n,
+                Normal::Back(_) => panic!("hit the inside from outside"),
+            };
+            assert!((normal.length() - 1.0).abs() < 1e-12);
+            assert!(dot(&normal, &(hit.point - center)) > 0.0);
+
+            // perpendicular to the surface: to the images of the tangents in object space
+            let local = matrix.inverse().unwrap().transform_point(hit.point);
+            let helper = if local.x().abs() < 0.9 {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            let tangent = cross(&local, &helper);
+            for t in [tangent, cross(&local, &tangent)] {
+                let world = matrix.transform_vector(t).normalize();
+                assert!(dot(&normal, &world).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_scale_area_and_pdf() {
+        let scaled = Transform::new(unit_sphere(), Matrix4::scale(Vec3::new(2.0, 2.0, 2.0)));
+        let material = Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5))));
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0, material);
+        assert!((scaled.area().unwrap() - sphere.area().unwrap()).abs() < 1e-9);
+
+        let mut sampler = IndependentSampler::new(4);
+        let origin = Point3::new(0.0, 5.0, 1.0);
+        for direction in [Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.1, -2.0, -0.3)] {
+            let expected = sphere.pdf_value(&origin, &direction, &mut sampler);
+            let pdf = scaled.pdf_value(&origin, &direction, &mut sampler);
+            assert!(
+                (pdf - expected).abs() < 1e-9 * expected,
+                "{} {}",
+                pdf,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        // an ellipsoid, seen from close by
+        let object = Transform::new(unit_sphere(), composed());
+        let origin = composed().transform_point(Point3::new(0.0, 0.0, 3.0));
+        let mut sampler = IndependentSampler::new(5);
+        let n = 200_000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let direction = random_unit_vector(&mut sampler);
+            sum += object.pdf_value(&origin, &direction, &mut sampler);
+        }
+        let integral = sum * 4.0 * PI / n as f64;
+        assert!((integral - 1.0).abs() < 0.03, "{}", integral);
+
+        // and the directions it picks find the object
+        for _ in 0..1000 {
+            let direction = object.random(&origin, &mut sampler);
+            assert!(object.pdf_value(&origin, &direction, &mut sampler) > 0.0);
+        }
+    }
+}
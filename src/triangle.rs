@@ -0,0 +1,304 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hit::{area_pdf, HitRecord, Hittable, Normal};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::util::{cross, dot};
+use crate::vec3::{Point3, Vec3};
+
+// A triangle lying on an axis-aligned plane has a flat bounding box, pad it like the rectangles.
+const PADDING: f64 = 0.0001;
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    // optional per-vertex shading normals, the geometric normal is used if absent
+    normals: Option<[Vec3; 3]>,
+    // optional per-vertex texture coordinates, the barycentric coordinates are used if absent
+    uvs: Option<[(f64, f64); 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f64, f64); 3]>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            vertices,
+            normals,
+            uvs,
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller–Trumbore intersection
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_range: &RangeInclusive<f64>,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<HitRecord> {
+        let [p0, p1, p2] = self.vertices;
+        let direction = ray.direction();
+
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+        let pvec = cross(&direction, &edge2);
+        let det = dot(&edge1, &pvec);
+        // the ray is parallel to the triangle
+        if det.abs() < 1E-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.origin() - p0;
+        let b1 = dot(&tvec, &pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = cross(&tvec, &edge1);
+        let b2 = dot(&direction, &qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = dot(&edge2, &qvec) * inv_det;
+        if !t_range.contains(&t) {
+            return None;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        let geometric_normal = cross(&edge1, &edge2).normalize();
+        let normal = match &self.normals {
+            Some([n0, n1, n2]) => {
+                let n = (*n0 * b0 + *n1 * b1 + *n2 * b2).normalize();
+                // keep the shading normal on the same side as the geometry
+                if dot(&n, &geometric_normal) < 0.0 {
+                    -n
+                } else {
+                    n
+                }
+            }
+            None => geometric_normal,
+        };
+        let (u, v) = match &self.uvs {
+            Some([uv0, uv1, uv2]) => (
+                uv0.0 * b0 + uv1.0 * b1 + uv2.0 * b2,
+                uv0.1 * b0 + uv1.1 * b1 + uv2.1 * b2,
+            ),
+            None => (b1, b2),
+        };
+
+        Some(HitRecord {
+            point: ray.at(t),
+            t,
+            normal: if dot(&direction, &geometric_normal) < 0.0 {
+                Normal::Front(normal)
+            } else {
+                Normal::Back(-normal)
+            },
+            material: self.material.clone(),
+            u,
+            v,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let [p0, p1, p2] = self.vertices;
+        let mut minimum = Point3::default();
+        let mut maximum = Point3::default();
+        for i in 0..3 {
+            minimum[i] = p0[i].min(p1[i]).min(p2[i]) - PADDING;
+            maximum[i] = p0[i].max(p1[i]).max(p2[i]) + PADDING;
+        }
+        Some(AABB::new(minimum, maximum))
+    }
+
+    fn area(&self) -> Option<f64> {
+        let [p0, p1, p2] = self.vertices;
+        Some(cross(&(p1 - p0), &(p2 - p0)).length() / 2.0)
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, sampler: &mut dyn Sampler) -> f64 {
+        let ray = Ray::new(*origin, *direction, 0.0);
+        match self.hit(&ray, &RangeInclusive::new(0.001, f64::INFINITY), sampler) {
+            Some(hit) => {
+                let [p0, p1, p2] = self.vertices;
+                let normal = cross(&(p1 - p0), &(p2 - p0));
+                let area = normal.length() / 2.0;
+                area_pdf(hit.t, direction, &normal.normalize(), area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        // uniform over the area, folding the square onto the triangle
+        let [p0, p1, p2] = self.vertices;
+        let (r1, r2) = sampler.get_2d();
+        let s = r1.sqrt();
+        let (b1, b2) = (s * (1.0 - r2), s * r2);
+        p0 + (p1 - p0) * b1 + (p2 - p0) * b2 - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::IndependentSampler;
+    use crate::texture::SolidColor;
+    use crate::util::random_unit_vector;
+    use std::f64::consts::PI;
+
+    fn material() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Box::new(SolidColor::new(0.5, 0.5, 0.5))))
+    }
+
+    // in the z = 0 plane, facing +z
+    fn triangle(uvs: Option<[(f64, f64); 3]>) -> Triangle {
+        Triangle::new(
+            [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(2.0, 0.0, 0.0),
+                Point3::new(0.0, 2.0, 0.0),
+            ],
+            None,
+            uvs,
+            material(),
+        )
+    }
+
+    fn shoot(triangle: &Triangle, x: f64, y: f64, dz: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Point3::new(x, y, -dz), Vec3::new(0.0, 0.0, dz), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        triangle.hit(
+            &ray,
+            &RangeInclusive::new(0.001, f64::INFINITY),
+            &mut sampler,
+        )
+    }
+
+    #[test]
+    fn hit_and_miss() {
+        let triangle = triangle(None);
+        let hit = shoot(&triangle, 0.5, 0.5, -1.0).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-12);
+        assert!((hit.point - Point3::new(0.5, 0.5, 0.0)).length() < 1e-12);
+        match hit.normal {
+            Normal::Front(n) => assert!((n - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12),
+            Normal::Back(_) => panic!("hit the front from the back"),
+        }
+
+        // outside each edge, and beyond the range
+        assert!(shoot(&triangle, -0.1, 0.5, -1.0).is_none());
+        assert!(shoot(&triangle, 0.5, -0.1, -1.0).is_none());
+        assert!(shoot(&triangle, 1.1, 1.0, -1.0).is_none());
+        let ray = Ray::new(Point3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut sampler = IndependentSampler::new(0);
+        assert!(triangle
+            .hit(&ray, &RangeInclusive::new(0.001, 0.5), &mut sampler)
+            .is_none());
+
+        // parallel to the plane
+        let ray = Ray::new(Point3::new(-1.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert!(triangle
+            .hit(
+                &ray,
+                &RangeInclusive::new(0.001, f64::INFINITY),
+                &mut sampler
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn edges_and_vertices() {
+        let triangle = triangle(None);
+        for (x, y) in [
+            (1.0, 0.0),
+            (0.0, 1.0),
+            (1.0, 1.0),
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (0.0, 2.0),
+        ] {
+            assert!(shoot(&triangle, x, y, -1.0).is_some(), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn back_face() {
+        let triangle = triangle(None);
+        let hit = shoot(&triangle, 0.5, 0.5, 1.0).unwrap();
+        assert!((hit.t - 1.0).abs() < 1e-12);
+        match hit.normal {
+            Normal::Back(n) => assert!((n - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12),
+            Normal::Front(_) => panic!("hit the back from the front"),
+        }
+    }
+
+    #[test]
+    fn barycentric_uvs() {
+        let hit = shoot(&triangle(None), 0.5, 1.0, -1.0).unwrap();
+        assert!((hit.u - 0.25).abs() < 1e-12 && (hit.v - 0.5).abs() < 1e-12);
+
+        let uvs = [(0.2, 0.1), (1.0, 0.1), (0.2, 0.9)];
+        for (x, y, u, v) in [
+            (0.0, 0.0, 0.2, 0.1),
+            (2.0, 0.0, 1.0, 0.1),
+            (0.0, 2.0, 0.2, 0.9),
+            (0.5, 1.0, 0.4, 0.5),
+        ] {
+            let hit = shoot(&triangle(Some(uvs)), x, y, -1.0).unwrap();
+            assert!((hit.u - u).abs() < 1e-12, "u at ({}, {}): {}", x, y, hit.u);
+            assert!((hit.v - v).abs() < 1e-12, "v at ({}, {}): {}", x, y, hit.v);
+        }
+    }
+
+    #[test]
+    fn area_and_pdf() {
+        let triangle = triangle(None);
+        assert!((triangle.area().unwrap() - 2.0).abs() < 1e-12);
+
+        // straight on from a distance of 2: distance² / (cos · area)
+        let mut sampler = IndependentSampler::new(1);
+        let origin = Point3::new(0.5, 0.5, 2.0);
+        let pdf = triangle.pdf_value(&origin, &Vec3::new(0.0, 0.0, -3.0), &mut sampler);
+        assert!((pdf - 2.0).abs() < 1e-12, "{}", pdf);
+        let away = triangle.pdf_value(&origin, &Vec3::new(0.0, 0.0, 1.0), &mut sampler);
+        assert_eq!(away, 0.0);
+
+        // the pdf integrates to one over the sphere of directions
+        let origin = Point3::new(0.5, 0.5, 0.5);
+        let n = 200_000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let direction = random_unit_vector(&mut sampler);
+            sum += triangle.pdf_value(&origin, &direction, &mut sampler);
+        }
+        let integral = sum * 4.0 * PI / n as f64;
+        assert!((integral - 1.0).abs() < 0.03, "{}", integral);
+
+        // and the directions it picks land on the triangle, evenly over its area
+        let mut near_corner = 0;
+        for _ in 0..10_000 {
+            let direction = triangle.random(&origin, &mut sampler);
+            let point = origin + direction;
+            assert!(point.z().abs() < 1e-12);
+            assert!(point.x() >= 0.0 && point.y() >= 0.0 && point.x() + point.y() <= 2.0);
+            if point.x() + point.y() < 1.0 {
+                near_corner += 1;
+            }
+        }
+        // the corner triangle is a quarter of the area
+        assert!((2_250..2_750).contains(&near_corner), "{}", near_corner);
+    }
+}
@@ -1,8 +1,11 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
 use std::ops::Range;
 
+use crate::sampler::Sampler;
 use crate::vec3::{Point3, Vec3};
 
-use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 
 pub fn dot(u: &Vec3, v: &Vec3) -> f64 {
     u.x() * v.x() + u.y() * v.y() + u.z() * v.z()
@@ -16,54 +19,80 @@ pub fn cross(u: &Vec3, v: &Vec3) -> Vec3 {
     )
 }
 
-pub fn random_usize_range(range: Range<usize>) -> usize {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(range)
+// Mixes the values into the seed, for random numbers that depend on nothing else.
+pub fn hash(seed: u64, values: &[u64]) -> u64 {
+    // splitmix64 finalizer, mixing each input before adding the next
+    let mix = |mut z: u64| {
+        z = z.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    values.iter().fold(mix(seed), |h, &v| mix(h ^ v))
 }
 
-pub fn random_f64() -> f64 {
-    random_f64_range(0.0..1.0)
+// An independent stream of random numbers for one sample of one pixel, so that the image does
+// not depend on which thread renders which pixel, or in which order.
+pub fn sample_rng(seed: u64, x: usize, y: usize, sample: usize) -> SmallRng {
+    SmallRng::seed_from_u64(hash(seed, &[x as u64, y as u64, sample as u64]))
 }
 
-pub fn random_f64_range(range: Range<f64>) -> f64 {
-    let mut rng = rand::thread_rng();
+pub fn random_usize_range(range: Range<usize>, rng: &mut SmallRng) -> usize {
     rng.gen_range(range)
 }
 
-pub fn random_in_unit_sphere() -> Point3 {
-    let mut rng = rand::thread_rng();
-    loop {
-        let p = Point3::new(
-            rng.gen_range(-1.0..1.0),
-            rng.gen_range(-1.0..1.0),
-            rng.gen_range(-1.0..1.0),
-        );
-        if p.length_squared() > 1.0 {
-            continue;
-        }
-        return p;
-    }
+// The helpers below map the next dimensions of the sample, without rejecting any, which would
+// throw away the even spread of low-discrepancy samples.
+
+pub fn random_f64(sampler: &mut dyn Sampler) -> f64 {
+    sampler.get_1d()
+}
+
+pub fn random_f64_range(range: Range<f64>, sampler: &mut dyn Sampler) -> f64 {
+    range.start + (range.end - range.start) * sampler.get_1d()
 }
 
-pub fn random_in_unit_disk() -> Point3 {
-    let mut rng = rand::thread_rng();
-    loop {
-        let p = Point3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-        if p.length_squared() > 1.0 {
-            continue;
-        }
-        return p;
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Point3 {
+    // uniform in volume, the radius grows as the cube root
+    random_unit_vector(sampler) * sampler.get_1d().cbrt()
+}
+
+// Shirley's concentric mapping of the square onto the disk, which keeps neighboring samples
+// close.
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Point3 {
+    let (u, v) = sampler.get_2d();
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Point3::new(0.0, 0.0, 0.0);
     }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    Point3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+// A direction around the z axis, distributed as its cosine with it, from points picked uniformly
+// on the unit disk and lifted onto the hemisphere.
+pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u, v) = sampler.get_2d();
+    let phi = 2.0 * PI * u;
+    let r = v.sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - v).sqrt())
 }
 
-// True Lambertian Reflection is achieved by picking random points on the surface of the unit sphere, offset along the surface normal.
-// Picking random points on the unit sphere can be achieved by picking random points in the unit sphere, and then normalizing those.
-pub fn random_unit_vector() -> Vec3 {
-    random_in_unit_sphere().normalize()
+// Uniform on the unit sphere: the height is uniform along the axis, as is the area of the sphere.
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u, v) = sampler.get_2d();
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
 pub fn reflect(v: &Vec3, normal: &Vec3) -> Vec3 {
-    *v - *normal * dot(&v, &normal) * 2.0
+    *v - *normal * dot(v, normal) * 2.0
 }
 
 pub fn refract(v: &Vec3, normal: &Vec3, etai_over_etat: f64) -> Vec3 {
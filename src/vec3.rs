@@ -3,18 +3,6 @@ use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign,
 pub type Color = Vec3;
 pub type Point3 = Vec3;
 
-impl std::fmt::Display for Color {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} {} {}",
-            (255.0 * self.x().sqrt()).floor(),
-            (255.0 * self.y().sqrt()).floor(),
-            (255.0 * self.z().sqrt()).floor()
-        )
-    }
-}
-
 #[derive(Copy, Default, Clone)]
 pub struct Vec3(f64, f64, f64);
 
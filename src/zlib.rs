@@ -0,0 +1,513 @@
+// A small zlib (RFC 1950) / DEFLATE (RFC 1951) implementation, just enough to read and write PNG.
+
+const MAX_BITS: usize = 15;
+
+// Base lengths and extra bits for the length codes 257..285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base offsets and extra bits for the distance codes 0..29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order in which the code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, String> {
+        while self.bit_count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| "unexpected end of compressed data".to_owned())?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1_u64 << n) - 1) as u32;
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+// A canonical Huffman code, stored as the number of codes of each length and the symbols
+// ordered by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0_u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+
+        let mut offsets = [0_u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        // code, first code of the current length and its index into `symbols`
+        let mut code = 0_i32;
+        let mut first = 0_i32;
+        let mut index = 0_i32;
+        for len in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_owned())
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0_u8; 288];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let nlen = reader.bits(5)? as usize + 257;
+    let ndist = reader.bits(5)? as usize + 1;
+    let ncode = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0_u8; 19];
+    for &idx in &CODE_LENGTH_ORDER[..ncode] {
+        code_lengths[idx] = reader.bits(3)? as u8;
+    }
+    let code_huffman = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0_u8; nlen + ndist];
+    let mut i = 0;
+    while i < nlen + ndist {
+        let symbol = code_huffman.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err("repeat with no previous length".to_owned());
+                }
+                (lengths[i - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > nlen + ndist {
+            return Err("too many code lengths".to_owned());
+        }
+        for len in &mut lengths[i..i + repeat] {
+            *len = value;
+        }
+        i += repeat;
+    }
+
+    Ok((
+        Huffman::new(&lengths[..nlen]),
+        Huffman::new(&lengths[nlen..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let idx = symbol - 257;
+                let len =
+                    LENGTH_BASE[idx] as usize + reader.bits(LENGTH_EXTRA[idx] as u32)? as usize;
+                let dist_symbol = distances.decode(reader)? as usize;
+                if dist_symbol >= 30 {
+                    return Err("invalid distance code".to_owned());
+                }
+                let dist = DIST_BASE[dist_symbol] as usize
+                    + reader.bits(DIST_EXTRA[dist_symbol] as u32)? as usize;
+                if dist > out.len() {
+                    return Err("distance too far back".to_owned());
+                }
+                // the copy may overlap its own output, so go byte by byte
+                let start = out.len() - dist;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => return Err("invalid literal/length code".to_owned()),
+        }
+    }
+}
+
+// Decompresses a raw DEFLATE stream.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader::new(data);
+    let mut out = vec![];
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let pos = reader.pos;
+                if pos + 4 > data.len() {
+                    return Err("unexpected end of compressed data".to_owned());
+                }
+                let len = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+                let nlen = u16::from_le_bytes([data[pos + 2], data[pos + 3]]) as usize;
+                if len != !nlen & 0xffff {
+                    return Err("stored block length mismatch".to_owned());
+                }
+                if pos + 4 + len > data.len() {
+                    return Err("unexpected end of compressed data".to_owned());
+                }
+                out.extend_from_slice(&data[pos + 4..pos + 4 + len]);
+                reader.pos = pos + 4 + len;
+            }
+            1 => {
+                let (lengths, distances) = fixed_tables();
+                inflate_block(&mut reader, &mut out, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &lengths, &distances)?;
+            }
+            _ => return Err("invalid block type".to_owned()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// Decompresses a zlib stream, checking its header and Adler-32 checksum.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6 {
+        return Err("zlib stream too short".to_owned());
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) {
+        return Err("invalid zlib header".to_owned());
+    }
+    if flg & 0x20 != 0 {
+        return Err("preset dictionaries are not supported".to_owned());
+    }
+
+    let out = inflate(&data[2..])?;
+    let checksum = u32::from_be_bytes([
+        data[data.len() - 4],
+        data[data.len() - 3],
+        data[data.len() - 2],
+        data[data.len() - 1],
+    ]);
+    if adler32(&out) != checksum {
+        return Err("zlib checksum mismatch".to_owned());
+    }
+    Ok(out)
+}
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+// How many earlier occurrences of a hash are tried when looking for a match
+const MAX_CHAIN: usize = 64;
+
+struct BitWriter {
+    out: Vec<u8>,
+    bit_buf: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            out: vec![],
+            bit_buf: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, value: u32, count: u32) {
+        self.bit_buf |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.out.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit.
+    fn code(&mut self, code: u32, len: u32) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.out.push(self.bit_buf as u8);
+        }
+        self.out
+    }
+}
+
+// Writes a literal/length symbol with the fixed Huffman code.
+fn write_symbol(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, len: usize, dist: usize) {
+    let i = LENGTH_BASE
+        .iter()
+        .rposition(|&b| b as usize <= len)
+        .unwrap();
+    write_symbol(writer, 257 + i as u32);
+    writer.bits(
+        (len - LENGTH_BASE[i] as usize) as u32,
+        LENGTH_EXTRA[i] as u32,
+    );
+
+    let d = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+    writer.code(d as u32, 5);
+    writer.bits((dist - DIST_BASE[d] as usize) as u32, DIST_EXTRA[d] as u32);
+}
+
+// Finds earlier occurrences of the three bytes at a position through hash chains.
+struct Matcher<'a> {
+    data: &'a [u8],
+    // the latest position with each hash
+    head: Vec<usize>,
+    // the previous position with the same hash, for the positions in the window
+    prev: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            prev: vec![usize::MAX; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let d = self.data;
+        let h = (d[pos] as u32) << 16 | (d[pos + 1] as u32) << 8 | d[pos + 2] as u32;
+        (h.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.data.len() {
+            let h = self.hash(pos);
+            self.prev[pos % WINDOW_SIZE] = self.head[h];
+            self.head[h] = pos;
+        }
+    }
+
+    // The length and distance of the longest match for `pos`, with a length of 0 if none.
+    fn longest_match(&self, pos: usize) -> (usize, usize) {
+        let data = self.data;
+        let max_len = MAX_MATCH.min(data.len() - pos);
+        if max_len < MIN_MATCH {
+            return (0, 0);
+        }
+
+        let (mut best_len, mut best_dist) = (0, 0);
+        let mut candidate = self.head[self.hash(pos)];
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || pos - candidate > WINDOW_SIZE {
+                break;
+            }
+            let len = (0..max_len)
+                .take_while(|&i| data[candidate + i] == data[pos + i])
+                .count();
+            if len > best_len {
+                best_len = len;
+                best_dist = pos - candidate;
+                if len == max_len {
+                    break;
+                }
+            }
+            let next = self.prev[candidate % WINDOW_SIZE];
+            // the slot may have been reused by a newer position
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        if best_len < MIN_MATCH {
+            (0, 0)
+        } else {
+            (best_len, best_dist)
+        }
+    }
+}
+
+// Stores the data uncompressed, in blocks of at most 64K.
+fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        return vec![0x01, 0x00, 0x00, 0xff, 0xff];
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+// Compresses into a raw DEFLATE stream: a single block with the fixed Huffman codes, or stored
+// blocks if the data does not compress.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    // last block, fixed Huffman codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let mut matcher = Matcher::new(data);
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, dist) = matcher.longest_match(pos);
+        if len == 0 {
+            write_symbol(&mut writer, data[pos] as u32);
+            matcher.insert(pos);
+            pos += 1;
+        } else {
+            write_match(&mut writer, len, dist);
+            for i in pos..pos + len {
+                matcher.insert(i);
+            }
+            pos += len;
+        }
+    }
+    write_symbol(&mut writer, 256);
+
+    let compressed = writer.finish();
+    if compressed.len() > data.len() + 5 * data.len().div_ceil(0xffff).max(1) {
+        deflate_stored(data)
+    } else {
+        compressed
+    }
+}
+
+// Compresses into a zlib stream.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, no preset dictionary
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_block() {
+        // zlib header, a final stored block holding "abc" and its checksum
+        let mut data = vec![0x78, 0x01, 1, 3, 0, 0xfc, 0xff, b'a', b'b', b'c'];
+        data.extend_from_slice(&adler32(b"abc").to_be_bytes());
+        assert_eq!(decompress(&data).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn malformed_streams() {
+        assert!(decompress(&[0x78, 0x01]).is_err());
+        // not deflate
+        assert!(decompress(&[0x77, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]).is_err());
+        // preset dictionary
+        assert!(decompress(&[0x78, 0x20, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]).is_err());
+        // wrong checksum
+        assert!(decompress(&[0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 2]).is_err());
+
+        // stored block lengths that do not match, or run past the end
+        assert!(inflate(&[1, 3, 0, 0xfb, 0xff, b'a', b'b', b'c']).is_err());
+        assert!(inflate(&[1, 3, 0, 0xfc, 0xff, b'a']).is_err());
+        // reserved block type
+        assert!(inflate(&[0x07]).is_err());
+        // a fixed Huffman block cut off before its end
+        assert!(inflate(&[0x03]).is_err());
+        assert!(inflate(&[]).is_err());
+    }
+}